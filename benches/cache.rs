@@ -0,0 +1,44 @@
+//! decoding a league worth of cached items, as json and as zstd compressed `MessagePack`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde_json::Value;
+
+use poe_api::schema::{CacheFormat, Item, League};
+
+/// the accessories fixture repeated to the size of every item endpoint of a league
+fn items() -> Vec<Item> {
+    let mut raw: Value =
+        serde_json::from_str(include_str!("../src/schema/jewelry.json")).expect("fixture");
+    let items: Vec<Item> = serde_json::from_value(raw["lines"].take()).expect("items");
+    items
+        .iter()
+        .cycle()
+        .take(items.len() * 30)
+        .cloned()
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let items = items();
+    let endpoints = vec!["UniqueAccessory".to_string()];
+
+    let mut group = c.benchmark_group("decode cached items");
+    for (name, format) in [("json", CacheFormat::Json), ("zstd", CacheFormat::Zstd)] {
+        let bytes = format
+            .encode(League::Standard, &endpoints, 0, &items)
+            .expect("encode");
+        println!("{name}: {} bytes", bytes.len());
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                format
+                    .decode::<Vec<Item>>(&bytes, League::Standard, &endpoints)
+                    .expect("decode")
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
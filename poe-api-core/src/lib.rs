@@ -1,14 +1,141 @@
-use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
-use syn::{DeriveInput, Ident};
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{spanned::Spanned, DeriveInput, Ident};
+
+/// options for `#[gql(where(...))]`
+#[derive(deluxe::ParseMetaItem, Default, Debug)]
+struct WhereArgs {
+    /// filter the field by a projection to one of its own fields, e.g. `via = "text"`
+    via: Option<syn::LitStr>,
+    /// filter type prefix, e.g. `filter = "Int"` for `IntFilter`. names the filter of a
+    /// `via` projection, or of a field type without a built-in filter such as a
+    /// `GQLFilter` enum
+    filter: Option<syn::LitStr>,
+}
+
+/// options for `#[gql(orderby(...))]`
+#[derive(deluxe::ParseMetaItem, Default, Debug)]
+struct OrderbyArgs {
+    /// sort by the result of a function taking the field, e.g. `key = "path::to::fn"`
+    key: Option<syn::LitStr>,
+}
+
+/// an attribute that can be given as a bare flag or with arguments, None if absent
+#[derive(Debug)]
+struct FlagArgs<T>(Option<T>);
+
+impl<T> Default for FlagArgs<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T: deluxe::ParseMetaItem + Default> deluxe::ParseMetaItem for FlagArgs<T> {
+    fn parse_meta_item(
+        input: syn::parse::ParseStream,
+        mode: deluxe::ParseMode,
+    ) -> deluxe::Result<Self> {
+        T::parse_meta_item(input, mode).map(|args| Self(Some(args)))
+    }
+
+    fn parse_meta_item_inline<'s, S: std::borrow::Borrow<syn::parse::ParseBuffer<'s>>>(
+        inputs: &[S],
+        mode: deluxe::ParseMode,
+    ) -> deluxe::Result<Self> {
+        T::parse_meta_item_inline(inputs, mode).map(|args| Self(Some(args)))
+    }
+
+    fn parse_meta_item_flag(_span: proc_macro2::Span) -> deluxe::Result<Self> {
+        Ok(Self(Some(T::default())))
+    }
+}
+
+/// a sort key computed from the whole model,
+/// e.g. `#[gql(orderby_key(name = "chaos_per_listing", key = "Item::chaos_per_listing"))]`
+#[derive(deluxe::ParseMetaItem, Debug)]
+struct OrderbyKeyAttr {
+    name: syn::LitStr,
+    key: syn::LitStr,
+    desc: Option<syn::LitStr>,
+}
+
+#[derive(deluxe::ExtractAttributes, Default, Debug)]
+#[deluxe(attributes(gql))]
+struct GQLModelAttrs {
+    #[deluxe(default, append, rename = orderby_key)]
+    orderby_keys: Vec<OrderbyKeyAttr>,
+}
+
+impl OrderbyKeyAttr {
+    fn ident(&self) -> syn::Result<Ident> {
+        syn::parse_str(&self.name.value())
+            .map_err(|_| syn::Error::new(self.name.span(), "expected an identifier"))
+    }
+
+    fn key(&self) -> syn::Result<syn::ExprPath> {
+        parse_key_path(&self.key)
+    }
+
+    fn orderby_enum_field(&self) -> syn::Result<TokenStream> {
+        let name = self.ident()?;
+        let doc = self.desc.as_ref().map(|desc| quote! { #[doc = #desc] });
+        Ok(quote! { #doc #name(crate::schema::Orderby), })
+    }
+
+    fn orderbyinput_cmp_orderby_match(&self) -> syn::Result<TokenStream> {
+        let name = self.ident()?;
+        let key = self.key()?;
+        Ok(quote! {
+            Self::#name(v) => v.compare(&#key(a), &#key(b)),
+        })
+    }
+
+    fn orderbyinput_direction_match(&self) -> syn::Result<TokenStream> {
+        let name = self.ident()?;
+        Ok(quote! {
+            Self::#name(v) => *v,
+        })
+    }
+}
+
+fn parse_key_path(key: &syn::LitStr) -> syn::Result<syn::ExprPath> {
+    syn::parse_str(&key.value()).map_err(|_| {
+        syn::Error::new(
+            key.span(),
+            "expected a function path such as \"path::to::fn\"",
+        )
+    })
+}
 
 #[derive(deluxe::ExtractAttributes, Debug)]
 #[deluxe(attributes(gql))]
 struct GQLField {
     #[deluxe(default)]
-    r#where: bool,
+    r#where: FlagArgs<WhereArgs>,
+    #[deluxe(default)]
+    orderby: FlagArgs<OrderbyArgs>,
+    /// the field is a struct that also derives GQLModel, filtered and sorted with its
+    /// own generated inputs. the derive cannot tell from the type name alone
     #[deluxe(default)]
-    orderby: bool,
+    nested: bool,
+    /// graphql name of the generated where field and orderby variant
+    name: Option<syn::LitStr>,
+    /// graphql description, defaults to the doc comment of the field
+    desc: Option<syn::LitStr>,
+    /// deprecation reason for the generated where field and orderby variant
+    deprecation: Option<syn::LitStr>,
+}
+
+/// filter type prefix of the types with a filter in `crate::schema::filters`
+fn builtin_filter(ty: &str) -> Option<&'static str> {
+    match ty {
+        "String" => Some("String"),
+        "i32" => Some("Int"),
+        "f64" => Some("Float"),
+        "bool" => Some("Boolean"),
+        "Modifier" => Some("Modifier"),
+        _ => None,
+    }
 }
 
 fn syn_type_to_idents(ty: &syn::Type) -> Vec<String> {
@@ -40,81 +167,294 @@ fn syn_type_to_idents(ty: &syn::Type) -> Vec<String> {
 struct FieldInfo {
     name: String,
     ty: Vec<String>,
+    /// the field type, used to point errors at the field
+    syn_ty: syn::Type,
+    ty_span: Span,
+    /// doc comment lines of the field
+    docs: Vec<String>,
     attrs: GQLField,
 }
 
 impl FieldInfo {
     fn from_ast(ast: &mut DeriveInput) -> deluxe::Result<Vec<FieldInfo>> {
+        let fields = match &mut ast.data {
+            syn::Data::Struct(syn::DataStruct {
+                fields: syn::Fields::Named(fields),
+                ..
+            }) => fields,
+            syn::Data::Struct(data) => {
+                return Err(syn::Error::new(
+                    data.fields.span(),
+                    "GQLModel only supports structs with named fields",
+                ))
+            }
+            syn::Data::Enum(data) => {
+                return Err(syn::Error::new(
+                    data.enum_token.span,
+                    "GQLModel only supports structs with named fields",
+                ))
+            }
+            syn::Data::Union(data) => {
+                return Err(syn::Error::new(
+                    data.union_token.span,
+                    "GQLModel only supports structs with named fields",
+                ))
+            }
+        };
+
         let mut field_info = Vec::new();
+        let errors = deluxe::Errors::new();
 
-        if let syn::Data::Struct(data) = &mut ast.data {
-            for field in &mut data.fields {
-                let field_name = field.ident.as_ref().unwrap().to_string();
-                let attrs: GQLField = deluxe::extract_attributes(field)?;
+        for field in &mut fields.named {
+            let Some(field_ident) = &field.ident else {
+                continue;
+            };
+            let field_name = field_ident.to_string();
 
-                let ty_idents = syn_type_to_idents(&field.ty);
-                field_info.push(Self {
-                    name: field_name,
-                    ty: ty_idents,
-                    attrs,
-                });
+            let attrs: GQLField = match deluxe::extract_attributes(field) {
+                Ok(attrs) => attrs,
+                Err(err) => {
+                    errors.push_syn(err);
+                    continue;
+                }
+            };
+
+            let docs = field
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("doc"))
+                .filter_map(|attr| match &attr.meta {
+                    syn::Meta::NameValue(syn::MetaNameValue {
+                        value:
+                            syn::Expr::Lit(syn::ExprLit {
+                                lit: syn::Lit::Str(doc),
+                                ..
+                            }),
+                        ..
+                    }) => Some(doc.value().trim().to_string()),
+                    _ => None,
+                })
+                .collect();
+
+            let info = Self {
+                name: field_name,
+                docs,
+                ty: syn_type_to_idents(&field.ty),
+                syn_ty: field.ty.clone(),
+                ty_span: field.ty.span(),
+                attrs,
+            };
+
+            if let Err(err) = info.validate() {
+                errors.push_syn(err);
+                continue;
             }
-        } else {
-            panic!("Only structs with named fields are supported");
+
+            field_info.push(info);
         }
 
+        errors.check()?;
+
         Ok(field_info)
     }
 
-    fn where_struct_field(&self) -> Option<TokenStream> {
-        if !self.attrs.r#where {
-            return None;
+    /// rejects attribute combinations that would otherwise generate invalid code
+    fn validate(&self) -> syn::Result<()> {
+        let is_used = self.where_args().is_some() || self.orderby_args().is_some();
+        if !is_used {
+            return Ok(());
+        }
+
+        let unsupported = |msg: &str| Err(syn::Error::new_spanned(&self.syn_ty, msg));
+
+        if self.ty.is_empty() {
+            return unsupported(
+                "unsupported field type, expected a path such as `String`, `Option<T>` or `Vec<T>`",
+            );
+        }
+
+        let vec_pos = self.ty.iter().position(|ty| ty == "Vec");
+        if let Some(vec_pos) = vec_pos {
+            // the elements may be optional, as long as they are not collections themselves
+            let elements = match &self.ty[vec_pos + 1..] {
+                [option, elements @ ..] if option == "Option" => elements,
+                elements => elements,
+            };
+            let is_nested_collection = elements.iter().any(|ty| ty == "Option" || ty == "Vec");
+            if is_nested_collection && self.where_args().is_some() {
+                return unsupported(
+                    "nested collections inside a Vec field are not supported, \
+                     expected `Vec<T>` or `Vec<Option<T>>`",
+                );
+            }
+            if self.attrs.nested {
+                return unsupported("`nested` is not supported on Vec fields");
+            }
+        }
+
+        if let Some(key) = self.orderby_args().and_then(|args| args.key.as_ref()) {
+            if self.attrs.nested {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "`key` cannot be combined with `nested`",
+                ));
+            }
+            parse_key_path(key)?;
+        }
+
+        if let Some(args) = self.where_args() {
+            if let Some(filter) = &args.filter {
+                syn::parse_str::<Ident>(&filter.value()).map_err(|_| {
+                    syn::Error::new(
+                        filter.span(),
+                        "expected a filter type prefix such as \"Int\"",
+                    )
+                })?;
+            }
+
+            if let Some(via) = &args.via {
+                if self.attrs.nested {
+                    return Err(syn::Error::new(
+                        via.span(),
+                        "`via` cannot be combined with `nested`",
+                    ));
+                }
+                for segment in via.value().split('.') {
+                    syn::parse_str::<Ident>(segment).map_err(|_| {
+                        syn::Error::new(
+                            via.span(),
+                            "expected a field path such as \"text\" or \"a.b\"",
+                        )
+                    })?;
+                }
+            }
+
+            // a missing filter would otherwise surface as an unresolved type in the
+            // generated code
+            let ty = self.ty.last().map_or("", String::as_str);
+            let has_filter = args.via.is_some()
+                || args.filter.is_some()
+                || self.attrs.nested
+                || builtin_filter(ty).is_some();
+            if !has_filter {
+                return unsupported(&format!(
+                    "`{ty}` has no built-in filter, name the filter of a GQLFilter enum with \
+                     `where(filter = \"{ty}\")` or mark a GQLModel struct as `nested`"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// doc and graphql attributes shared by the where field and orderby variant
+    fn graphql_attrs(&self) -> TokenStream {
+        let desc = match &self.attrs.desc {
+            Some(desc) => Some(desc.value()),
+            None if !self.docs.is_empty() => Some(self.docs.join("\n")),
+            None => None,
+        };
+        let doc = desc.map(|desc| quote! { #[doc = #desc] });
+
+        let mut args = Vec::new();
+        if let Some(name) = &self.attrs.name {
+            args.push(quote! { name = #name });
+        }
+        if let Some(deprecation) = &self.attrs.deprecation {
+            args.push(quote! { deprecation = #deprecation });
         }
+        let graphql = (!args.is_empty()).then(|| quote! { #[graphql(#(#args),*)] });
+
+        quote! {
+            #doc
+            #graphql
+        }
+    }
+
+    fn where_args(&self) -> Option<&WhereArgs> {
+        self.attrs.r#where.0.as_ref()
+    }
+
+    fn orderby_args(&self) -> Option<&OrderbyArgs> {
+        self.attrs.orderby.0.as_ref()
+    }
+
+    /// tokens accessing the projected field for `where(via = "...")`
+    fn where_via(&self) -> Option<TokenStream> {
+        let via = self.where_args()?.via.as_ref()?.value();
+        let path = via.split('.').map(|segment| format_ident!("{}", segment));
+        Some(quote! { #(.#path)* })
+    }
+
+    fn where_struct_field(&self) -> Option<TokenStream> {
+        let args = self.where_args()?;
 
         // should be sufficient to handle the rightmost (innermost) type
         if let Some(ty) = self.ty.last() {
             let name = format_ident!("{}", &self.name);
-            let filter_prefix = match ty.as_str() {
-                "String" => "String",
-                "i32" => "Int",
-                "f64" => "Float",
-                "bool" => "Boolean",
-                _ => ty,
+            let attrs = self.graphql_attrs();
+
+            if self.attrs.nested {
+                let nested_where_ident = format_ident!("{}Where", ty, span = self.ty_span);
+                return Some(quote! { #attrs pub #name: Option<#nested_where_ident>, });
+            }
+
+            // a declared filter takes precedence, projections default to strings and
+            // validate() made sure the other types have a built-in filter
+            let filter = args.filter.as_ref().map(syn::LitStr::value);
+            let filter_prefix = match (&args.via, &filter) {
+                (_, Some(filter)) => filter.as_str(),
+                (Some(_), None) => "String",
+                (None, None) => builtin_filter(ty).unwrap_or(ty.as_str()),
             };
-            let filter_ident = format_ident!("{}Filter", filter_prefix);
-            return Some(quote! { pub #name: Option<crate::schema::filters::#filter_ident>, });
+            let filter_ident = format_ident!("{}Filter", filter_prefix, span = self.ty_span);
+            let filter_path =
+                quote_spanned! {self.ty_span=> crate::schema::filters::#filter_ident };
+
+            // lists are filtered as a whole with quantifiers over the inner filter
+            if self.ty.iter().any(|ty| ty == "Vec") {
+                return Some(quote! {
+                    #attrs
+                    pub #name: Option<crate::schema::filters::ListFilter<#filter_path>>,
+                });
+            }
+
+            return Some(quote! { #attrs pub #name: Option<#filter_path>, });
         }
 
         None
     }
 
     fn whereinput_filter_if_let(&self) -> Option<TokenStream> {
-        if !self.attrs.r#where {
-            return None;
-        }
+        self.where_args()?;
 
         let key = format_ident!("{}", &self.name);
         let item_value = quote! { item.#key };
 
+        let vec_pos = self.ty.iter().position(|ty| ty == "Vec");
+        // null elements are skipped, so the list filter only sees the values
+        let has_optional_elements = vec_pos
+            .and_then(|pos| self.ty.get(pos + 1))
+            .is_some_and(|ty| ty == "Option");
+        let elements = if has_optional_elements {
+            quote! { filter_value.iter().flatten() }
+        } else {
+            quote! { filter_value.iter() }
+        };
+        let leaf_value = match self.where_via() {
+            Some(via) if vec_pos.is_some() => quote! {
+                #elements.map(|v| v #via.to_owned()).collect::<Vec<_>>()
+            },
+            Some(via) => quote! { filter_value #via.to_owned() },
+            None if has_optional_elements => quote! { #elements.cloned().collect::<Vec<_>>() },
+            None => quote! { filter_value },
+        };
+
         let mut body = self
             .ty
             .iter()
             .rev()
             .fold(TokenStream::new(), |acc, ty| match ty.as_str() {
-                "Vec" => {
-                    quote! {
-                        let filter_value = #item_value.to_owned();
-                        if !#item_value.iter().any(|arr_item| {
-                            let filter_value = arr_item.to_owned();
-                            #acc
-                            else {
-                                return true;
-                            }
-                        }) {
-                            return false;
-                        }
-                    }
-                }
                 "Option" => {
                     quote! {
                         if let Some(_) = #item_value {
@@ -123,15 +463,16 @@ impl FieldInfo {
                         }
                     }
                 }
+                // a Vec is passed whole to its ListFilter, discarding the inner types
                 _ => quote! {
-                    if !filter_obj.filter_fn(filter_value) {
+                    if !filter_obj.filter_fn(#leaf_value) {
                         return false;
                     }
                 },
             });
 
-        // only a single token, filter value is the item directly
-        if self.ty.len() == 1 {
+        // no Option wrapper, filter value is the item directly
+        if self.ty.first().map(String::as_str) != Some("Option") {
             body = quote! {
                 let filter_value = #item_value.to_owned();
                 #body
@@ -149,26 +490,88 @@ impl FieldInfo {
     }
 
     fn orderby_enum_field(&self) -> Option<TokenStream> {
-        if !self.attrs.orderby {
-            return None;
-        }
+        self.orderby_args()?;
 
         let name = format_ident!("{}", &self.name);
-        Some(quote! { #name(crate::schema::Orderby), })
+        let attrs = self.graphql_attrs();
+
+        if self.attrs.nested {
+            let nested_orderby_ident =
+                format_ident!("{}Orderby", self.ty.last()?, span = self.ty_span);
+            return Some(quote! { #attrs #name(#nested_orderby_ident), });
+        }
+
+        Some(quote! { #attrs #name(crate::schema::Orderby), })
     }
 
     fn orderbyinput_cmp_orderby_match(&self) -> Option<TokenStream> {
-        if !self.attrs.orderby {
-            return None;
+        let args = self.orderby_args()?;
+
+        let name = format_ident!("{}", &self.name);
+
+        // key functions take the field as is, validated to parse in validate()
+        if let Some(key) = args.key.as_ref().and_then(|key| parse_key_path(key).ok()) {
+            return Some(quote! {
+                Self::#name(v) => v.compare(&#key(&a.#name), &#key(&b.#name)),
+            });
+        }
+
+        // collections are sorted by their length
+        match (
+            self.ty.first().map(String::as_str),
+            self.ty.get(1).map(String::as_str),
+        ) {
+            (Some("Vec"), _) => {
+                return Some(quote! {
+                    Self::#name(v) => v.compare(&a.#name.len(), &b.#name.len()),
+                })
+            }
+            (Some("Option"), Some("Vec")) => {
+                return Some(quote! {
+                    Self::#name(v) => v.compare(
+                        &a.#name.as_ref().map(Vec::len),
+                        &b.#name.as_ref().map(Vec::len),
+                    ),
+                })
+            }
+            _ => {}
         }
 
+        if self.attrs.nested {
+            let is_option = self.ty.first().map(String::as_str) == Some("Option");
+            return Some(if is_option {
+                quote! {
+                    Self::#name(v) => crate::schema::orderby::OrderbyInput::cmp_orderby_option(
+                        v,
+                        a.#name.as_ref(),
+                        b.#name.as_ref(),
+                    ),
+                }
+            } else {
+                quote! {
+                    Self::#name(v) => crate::schema::orderby::OrderbyInput::cmp_orderby(v, &a.#name, &b.#name),
+                }
+            });
+        }
+
+        Some(quote! {
+            Self::#name(v) => v.compare(&a.#name, &b.#name),
+        })
+    }
+
+    fn orderbyinput_direction_match(&self) -> Option<TokenStream> {
+        self.orderby_args()?;
+
         let name = format_ident!("{}", &self.name);
 
+        if self.attrs.nested {
+            return Some(quote! {
+                Self::#name(v) => crate::schema::orderby::OrderbyInput::direction(v),
+            });
+        }
+
         Some(quote! {
-            Self::#name(v)=> match v {
-                crate::schema::Orderby::Asc => a.#name.partial_cmp(&b.#name).unwrap(),
-                crate::schema::Orderby::Desc => a.#name.partial_cmp(&b.#name).unwrap().reverse(),
-            },
+            Self::#name(v) => *v,
         })
     }
 }
@@ -224,47 +627,73 @@ fn impl_whereinput(fields: &[FieldInfo], model_ident: &Ident) -> TokenStream {
                 self.not.as_ref()
             }
 
-            fn filter(&self, items: Vec<Self::Output>) -> Vec<Self::Output> {
-                items.into_iter().filter(|item| {
-                    #(#whereinput_filter_if_let)*
-                    return true;
-                }).collect()
+            fn matches(&self, item: &Self::Output) -> bool {
+                #(#whereinput_filter_if_let)*
+                true
+            }
+        }
+
+        // allows the model to be filtered as a nested field of another model
+        impl crate::schema::filters::FilterInput for #where_ident {
+            type Item = #model_ident;
+
+            fn filter_fn(&self, s: Self::Item) -> bool {
+                crate::schema::filters::WhereInput::matches_recursive(self, &s)
             }
         }
     }
 }
 
-fn orderby_enum(fields: &[FieldInfo], model_ident: &Ident) -> TokenStream {
+fn orderby_enum(
+    fields: &[FieldInfo],
+    model_attrs: &GQLModelAttrs,
+    model_ident: &Ident,
+) -> syn::Result<TokenStream> {
     if fields.is_empty() {
-        return quote! {};
+        return Ok(quote! {});
     }
 
     let orderby_ident = format_ident!("{}Orderby", model_ident);
-    let enum_fields = fields
+    let mut enum_fields = fields
         .iter()
         .filter_map(|info| info.orderby_enum_field())
         .collect::<Vec<_>>();
+    for orderby_key in &model_attrs.orderby_keys {
+        enum_fields.push(orderby_key.orderby_enum_field()?);
+    }
 
-    quote! {
+    Ok(quote! {
         #[derive(Debug, async_graphql::OneofObject)]
         pub enum #orderby_ident {
             #(#enum_fields)*
         }
-    }
+    })
 }
 
-fn impl_orderbyinput(fields: &[FieldInfo], model_ident: &Ident) -> TokenStream {
+fn impl_orderbyinput(
+    fields: &[FieldInfo],
+    model_attrs: &GQLModelAttrs,
+    model_ident: &Ident,
+) -> syn::Result<TokenStream> {
     if fields.is_empty() {
-        return quote! {};
+        return Ok(quote! {});
     }
 
     let orderby_ident = format_ident!("{}Orderby", model_ident);
-    let cmp_orderby_match = fields
+    let mut cmp_orderby_match = fields
         .iter()
         .filter_map(|info| info.orderbyinput_cmp_orderby_match())
         .collect::<Vec<_>>();
+    let mut direction_match = fields
+        .iter()
+        .filter_map(|info| info.orderbyinput_direction_match())
+        .collect::<Vec<_>>();
+    for orderby_key in &model_attrs.orderby_keys {
+        cmp_orderby_match.push(orderby_key.orderbyinput_cmp_orderby_match()?);
+        direction_match.push(orderby_key.orderbyinput_direction_match()?);
+    }
 
-    quote! {
+    Ok(quote! {
         impl crate::schema::orderby::OrderbyInput for #orderby_ident {
             type Output = #model_ident;
 
@@ -275,20 +704,27 @@ fn impl_orderbyinput(fields: &[FieldInfo], model_ident: &Ident) -> TokenStream {
                     _ => panic!("Unreachable: empty orderby!"),
                 }
             }
+
+            fn direction(&self) -> crate::schema::Orderby {
+                match self {
+                    #(#direction_match)*
+                }
+            }
         }
-    }
+    })
 }
 
 pub fn gqlmodel_core(item: TokenStream) -> deluxe::Result<TokenStream> {
-    let mut ast: DeriveInput = syn::parse2(item).unwrap();
+    let mut ast: DeriveInput = syn::parse2(item)?;
 
     let model_ident = ast.ident.to_owned();
+    let model_attrs: GQLModelAttrs = deluxe::extract_attributes(&mut ast)?;
     let fields_info = FieldInfo::from_ast(&mut ast)?;
 
     let where_struct = where_struct(&fields_info, &model_ident);
     let impl_whereinput = impl_whereinput(&fields_info, &model_ident);
-    let orderby_enum = orderby_enum(&fields_info, &model_ident);
-    let impl_orderbyinput = impl_orderbyinput(&fields_info, &model_ident);
+    let orderby_enum = orderby_enum(&fields_info, &model_attrs, &model_ident)?;
+    let impl_orderbyinput = impl_orderbyinput(&fields_info, &model_attrs, &model_ident)?;
 
     Ok(quote! {
         #where_struct
@@ -300,3 +736,51 @@ pub fn gqlmodel_core(item: TokenStream) -> deluxe::Result<TokenStream> {
         #impl_orderbyinput
     })
 }
+
+/// generates `{Enum}Filter` with equality and membership filters for a fieldless enum
+pub fn gqlfilter_core(item: TokenStream) -> deluxe::Result<TokenStream> {
+    let ast: DeriveInput = syn::parse2(item)?;
+
+    let unit_only = match &ast.data {
+        syn::Data::Enum(data) => data
+            .variants
+            .iter()
+            .find(|variant| !matches!(variant.fields, syn::Fields::Unit))
+            .map_or(Ok(()), |variant| Err(variant.fields.span())),
+        syn::Data::Struct(data) => Err(data.struct_token.span),
+        syn::Data::Union(data) => Err(data.union_token.span),
+    };
+    if let Err(span) = unit_only {
+        return Err(syn::Error::new(
+            span,
+            "GQLFilter only supports enums with unit variants",
+        ));
+    }
+
+    let enum_ident = ast.ident;
+    let filter_ident = format_ident!("{}Filter", enum_ident);
+
+    Ok(quote! {
+        #[derive(Debug, async_graphql::InputObject)]
+        pub struct #filter_ident {
+            pub _eq: Option<#enum_ident>,
+            pub _ne: Option<#enum_ident>,
+            pub _in: Option<Vec<#enum_ident>>,
+            pub _nin: Option<Vec<#enum_ident>>,
+        }
+
+        impl crate::schema::filters::FilterInput for #filter_ident {
+            type Item = #enum_ident;
+
+            fn filter_fn(&self, s: Self::Item) -> bool {
+                match &self {
+                    Self { _eq: Some(v), .. } if &s != v => false,
+                    Self { _ne: Some(v), .. } if &s == v => false,
+                    Self { _in: Some(v), .. } if !v.contains(&s) => false,
+                    Self { _nin: Some(v), .. } if v.contains(&s) => false,
+                    _ => true,
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,175 @@
+use poe_api_core::{gqlfilter_core, gqlmodel_core};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// pretty prints the generated code so snapshots are readable
+fn expand(f: fn(TokenStream) -> syn::Result<TokenStream>, item: TokenStream) -> String {
+    let tokens = f(item).expect("derive should succeed");
+    let file = syn::parse2::<syn::File>(tokens).expect("generated code should parse");
+    prettyplease::unparse(&file)
+}
+
+#[test]
+fn primitives() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where, orderby)]
+                pub name: String,
+                #[gql(where, orderby)]
+                pub count: i32,
+                #[gql(where, orderby)]
+                pub value: f64,
+                #[gql(where, orderby)]
+                pub corrupted: bool,
+                pub ignored: String,
+            }
+        }
+    ));
+}
+
+#[test]
+fn options() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where, orderby)]
+                pub base_type: Option<String>,
+                #[gql(where, orderby)]
+                pub links: Option<i32>,
+            }
+        }
+    ));
+}
+
+#[test]
+fn vecs() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where)]
+                pub modifiers: Vec<Modifier>,
+                #[gql(where)]
+                pub tags: Option<Vec<String>>,
+                #[gql(where)]
+                pub data: Vec<Option<f64>>,
+            }
+        }
+    ));
+}
+
+#[test]
+fn orderby_collections_and_keys() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            #[gql(orderby_key(name = "chaos_per_listing", key = "Model::chaos_per_listing"))]
+            #[gql(orderby_key(name = "spread", key = "spread", desc = "max minus min value"))]
+            pub struct Model {
+                #[gql(orderby)]
+                pub modifiers: Vec<Modifier>,
+                #[gql(orderby)]
+                pub tags: Option<Vec<String>>,
+                #[gql(orderby(key = "total_change"))]
+                pub sparkline: Sparkline,
+                #[gql(orderby)]
+                pub data: Vec<Option<f64>>,
+            }
+        }
+    ));
+}
+
+#[test]
+fn enums() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where(filter = "ItemEndpoint"))]
+                pub endpoint: ItemEndpoint,
+            }
+        }
+    ));
+}
+
+#[test]
+fn where_and_orderby_combinations() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where)]
+                pub where_only: String,
+                #[gql(orderby)]
+                pub orderby_only: f64,
+                #[gql(where, orderby)]
+                pub both: i32,
+            }
+        }
+    ));
+}
+
+#[test]
+fn nested() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where, orderby, nested)]
+                pub sparkline: Sparkline,
+                #[gql(where, orderby, nested)]
+                pub pay: Option<Pay>,
+            }
+        }
+    ));
+}
+
+#[test]
+fn via() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                #[gql(where(via = "text"))]
+                pub modifiers: Vec<Modifier>,
+                #[gql(where(via = "total_change", filter = "Float"))]
+                pub sparkline: Option<Sparkline>,
+            }
+        }
+    ));
+}
+
+#[test]
+fn graphql_attributes() {
+    insta::assert_snapshot!(expand(
+        gqlmodel_core,
+        quote! {
+            pub struct Model {
+                /// price in chaos orbs
+                #[gql(where, orderby)]
+                pub chaos_value: f64,
+                /// ignored in favour of desc
+                #[gql(where, orderby, desc = "price in divine orbs")]
+                pub divine_value: f64,
+                #[gql(where, orderby, name = "type", deprecation = "use endpoint instead")]
+                pub item_type: Option<String>,
+            }
+        }
+    ));
+}
+
+#[test]
+fn enum_filter() {
+    insta::assert_snapshot!(expand(
+        gqlfilter_core,
+        quote! {
+            pub enum Endpoint {
+                Currency,
+                Fragment,
+            }
+        }
+    ));
+}
@@ -0,0 +1,8 @@
+use poe_api_derive::GQLModel;
+
+#[derive(GQLModel)]
+enum Item {
+    Name(String),
+}
+
+fn main() {}
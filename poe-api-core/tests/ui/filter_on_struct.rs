@@ -0,0 +1,8 @@
+use poe_api_derive::GQLFilter;
+
+#[derive(GQLFilter)]
+struct Endpoint {
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use poe_api_derive::GQLModel;
+
+struct Rarity;
+
+#[derive(GQLModel)]
+struct Item {
+    #[gql(where)]
+    rarity: Option<Rarity>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use poe_api_derive::GQLModel;
+
+#[derive(GQLModel)]
+struct Item {
+    #[gql(orderby(key = "not a path"))]
+    name: String,
+}
+
+fn main() {}
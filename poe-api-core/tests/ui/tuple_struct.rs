@@ -0,0 +1,6 @@
+use poe_api_derive::GQLModel;
+
+#[derive(GQLModel)]
+struct Item(String, f64);
+
+fn main() {}
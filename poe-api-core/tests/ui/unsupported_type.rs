@@ -0,0 +1,9 @@
+use poe_api_derive::GQLModel;
+
+#[derive(GQLModel)]
+struct Item {
+    #[gql(where)]
+    range: (i32, i32),
+}
+
+fn main() {}
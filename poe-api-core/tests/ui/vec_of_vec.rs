@@ -0,0 +1,9 @@
+use poe_api_derive::GQLModel;
+
+#[derive(GQLModel)]
+struct Sparkline {
+    #[gql(where)]
+    data: Vec<Option<Vec<f64>>>,
+}
+
+fn main() {}
@@ -1,8 +1,33 @@
-use poe_api_core::gqlmodel_core;
+use poe_api_core::{gqlfilter_core, gqlmodel_core};
 use proc_macro::TokenStream;
 
+/// generates `{Model}Where` and `{Model}Orderby` inputs from the fields marked with
+/// `#[gql(...)]`:
+///
+/// - `where` for `String`, `i32`, `f64`, `bool` and `Modifier` fields, options and
+///   lists of them. `where(filter = "ItemEndpoint")` names the filter of other types such
+///   as `GQLFilter` enums, `where(via = "text", filter = "Int")` filters a projection
+/// - `orderby`, optionally `orderby(key = "path::to::fn")` to sort by a computed key
+/// - `nested` for a struct field whose type also derives `GQLModel`, filtered with its
+///   `{Type}Where` and sorted with its `{Type}Orderby`. a derive only sees the field's
+///   type name, so nested models are marked rather than detected. in graphql the nested
+///   orderby reads `{ sparkline: { totalChange: DESC } }`, the rest `sort` parameter and
+///   the cli `--by` take the dotted path `sparkline.total_change`
+/// - `name`, `desc` and `deprecation` for the generated field and variant
+///
+/// `#[gql(orderby_key(name = "...", key = "path::to::fn"))]` on the struct adds an
+/// orderby variant computed from the whole model
 #[proc_macro_derive(GQLModel, attributes(gql))]
 // note it's proc_macro1 token stream
 pub fn gqlmodel_derive_macro2(item: TokenStream) -> TokenStream {
-    gqlmodel_core(item.into()).unwrap().into()
+    gqlmodel_core(item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(GQLFilter)]
+pub fn gqlfilter_derive_macro2(item: TokenStream) -> TokenStream {
+    gqlfilter_core(item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
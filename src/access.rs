@@ -0,0 +1,410 @@
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header::RETRY_AFTER, HeaderMap, HeaderValue},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+use crate::rest::ApiError;
+use crate::schema::{record_rejected, Quota};
+
+const DEFAULT_KEY_RATE_LIMIT: u32 = 600;
+const DEFAULT_IP_RATE_LIMIT: u32 = 60;
+/// requests are counted per minute
+const WINDOW: Duration = Duration::from_mins(1);
+/// clients tracked before the expired windows are dropped
+const PRUNE_AT: usize = 10_000;
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// `name:key`, or `name:key:limit` with its own requests per minute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    pub rate_limit: Option<u32>,
+}
+
+pub fn parse_api_key(value: &str) -> Result<ApiKey, String> {
+    let (name, rest) = value
+        .split_once(':')
+        .ok_or_else(|| format!("api key {value:?} is not name:key or name:key:limit"))?;
+    let (key, rate_limit) = match rest.rsplit_once(':') {
+        Some((key, limit)) => {
+            let limit = limit
+                .parse()
+                .map_err(|_| format!("rate limit of api key {name} is not a number"))?;
+            (key, Some(limit))
+        }
+        None => (rest, None),
+    };
+    if name.is_empty() || key.is_empty() {
+        return Err(format!("api key {value:?} has an empty name or key"));
+    }
+    Ok(ApiKey {
+        name: name.to_string(),
+        key: key.to_string(),
+        rate_limit,
+    })
+}
+
+/// who may use the api and how often
+#[derive(Debug, Clone, PartialEq, Eq, clap::Args)]
+pub struct AccessConfig {
+    /// api key as name:key, or name:key:limit with its own requests per minute.
+    /// sent in the `X-API-Key` header or the `api_key` query parameter
+    #[arg(
+        long = "api-key",
+        env = "POE_API_KEYS",
+        hide_env_values = true,
+        value_delimiter = ',',
+        value_parser = parse_api_key
+    )]
+    pub api_keys: Vec<ApiKey>,
+    /// requests per minute of each api key without a limit of its own
+    #[arg(long, default_value_t = DEFAULT_KEY_RATE_LIMIT)]
+    pub key_rate_limit: u32,
+    /// requests per minute of each address sending no api key
+    #[arg(long, default_value_t = DEFAULT_IP_RATE_LIMIT)]
+    pub ip_rate_limit: u32,
+    /// rejects requests without an api key
+    #[arg(long)]
+    pub deny_anonymous: bool,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            key_rate_limit: DEFAULT_KEY_RATE_LIMIT,
+            ip_rate_limit: DEFAULT_IP_RATE_LIMIT,
+            deny_anonymous: false,
+        }
+    }
+}
+
+/// what requests are counted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    /// name of the api key
+    Key(String),
+    /// peer address, unknown when the server is not given the connection info
+    Address(Option<IpAddr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    start: Instant,
+    used: u32,
+}
+
+/// fixed windows of a minute per client
+#[derive(Debug, Default)]
+struct RateLimiter {
+    windows: Mutex<HashMap<Client, Window>>,
+}
+
+/// whole seconds, rounded up
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+impl RateLimiter {
+    /// counts a request of the client, `Err` with the spent quota when it is over the limit
+    fn acquire(&self, client: &Client, limit: u32, now: Instant) -> Result<Quota, Quota> {
+        let mut windows = self.windows.lock().expect("rate limiter lock poisoned");
+        if windows.len() >= PRUNE_AT {
+            windows.retain(|_, window| now.duration_since(window.start) < WINDOW);
+        }
+        let window = windows.entry(client.clone()).or_insert(Window {
+            start: now,
+            used: 0,
+        });
+        if now.duration_since(window.start) >= WINDOW {
+            *window = Window {
+                start: now,
+                used: 0,
+            };
+        }
+        let allowed = window.used < limit;
+        if allowed {
+            window.used += 1;
+        }
+        let Window { start, used } = *window;
+        drop(windows);
+
+        let quota = Quota {
+            key: match client {
+                Client::Key(name) => Some(name.clone()),
+                Client::Address(_) => None,
+            },
+            limit,
+            remaining: limit.saturating_sub(used),
+            resets_in: ceil_secs(WINDOW.saturating_sub(now.duration_since(start))),
+        };
+        if allowed {
+            Ok(quota)
+        } else {
+            Err(quota)
+        }
+    }
+}
+
+struct Access {
+    /// name and requests per minute by key
+    keys: HashMap<String, (String, u32)>,
+    ip_rate_limit: u32,
+    deny_anonymous: bool,
+    limiter: RateLimiter,
+}
+
+/// requests are counted per key name, so names and keys have to be unique
+impl TryFrom<AccessConfig> for Access {
+    type Error = String;
+
+    fn try_from(config: AccessConfig) -> Result<Self, String> {
+        let mut names = HashSet::new();
+        let mut keys = HashMap::new();
+        for api_key in config.api_keys {
+            if !names.insert(api_key.name.clone()) {
+                return Err(format!(
+                    "api key name {} is used more than once",
+                    api_key.name
+                ));
+            }
+            let limit = api_key.rate_limit.unwrap_or(config.key_rate_limit);
+            if keys
+                .insert(api_key.key, (api_key.name.clone(), limit))
+                .is_some()
+            {
+                return Err(format!("api key {} has the key of another", api_key.name));
+            }
+        }
+        Ok(Self {
+            keys,
+            ip_rate_limit: config.ip_rate_limit,
+            deny_anonymous: config.deny_anonymous,
+            limiter: RateLimiter::default(),
+        })
+    }
+}
+
+impl Access {
+    /// the client of a request and its requests per minute
+    fn identify(
+        &self,
+        key: Option<&str>,
+        address: Option<IpAddr>,
+    ) -> Result<(Client, u32), ApiError> {
+        match key {
+            Some(key) => self
+                .keys
+                .get(key)
+                .map(|(name, limit)| (Client::Key(name.clone()), *limit))
+                .ok_or_else(|| ApiError::unauthorized("invalid api key")),
+            None if self.deny_anonymous => Err(ApiError::unauthorized(
+                "an api key is required, send it in the X-API-Key header \
+                 or the api_key query parameter",
+            )),
+            None => Ok((Client::Address(address), self.ip_rate_limit)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyParam {
+    api_key: Option<String>,
+}
+
+/// `X-API-Key` header, or the `api_key` query parameter
+fn api_key(request: &Request) -> Option<String> {
+    let key = match request.headers().get(API_KEY_HEADER) {
+        Some(value) => value.to_str().ok()?.to_string(),
+        None => {
+            Query::<KeyParam>::try_from_uri(request.uri())
+                .ok()?
+                .0
+                .api_key?
+        }
+    };
+    let key = key.trim();
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+fn set_quota_headers(headers: &mut HeaderMap, quota: &Quota) {
+    headers.insert("x-ratelimit-limit", HeaderValue::from(quota.limit));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(quota.remaining));
+    headers.insert("x-ratelimit-reset", HeaderValue::from(quota.resets_in));
+}
+
+/// rejects unknown keys, anonymous requests when they are denied and clients over
+/// their limit, and hands the quota left to the handlers
+async fn limit(State(access): State<Arc<Access>>, mut request: Request, next: Next) -> Response {
+    let address = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let (client, limit) = match access.identify(api_key(&request).as_deref(), address) {
+        Ok(client) => client,
+        Err(err) => {
+            record_rejected("unauthorized");
+            debug!(?address, error = %err, "request rejected");
+            return err.into_response();
+        }
+    };
+
+    match access.limiter.acquire(&client, limit, Instant::now()) {
+        Ok(quota) => {
+            request.extensions_mut().insert(quota.clone());
+            let mut response = next.run(request).await;
+            set_quota_headers(response.headers_mut(), &quota);
+            response
+        }
+        Err(quota) => {
+            record_rejected("rate_limited");
+            debug!(?client, "rate limited");
+            let mut response = ApiError::too_many_requests(format!(
+                "rate limit of {} requests per minute exceeded, retry in {} seconds",
+                quota.limit, quota.resets_in
+            ))
+            .into_response();
+            let headers = response.headers_mut();
+            set_quota_headers(headers, &quota);
+            headers.insert(RETRY_AFTER, HeaderValue::from(quota.resets_in));
+            response
+        }
+    }
+}
+
+/// requires an api key or counts requests per address, depending on the config.
+/// fails when two api keys share a name or a key
+///
+/// addresses are only known when served with `into_make_service_with_connect_info`
+pub fn protect(router: Router, config: AccessConfig) -> Result<Router, String> {
+    if config.deny_anonymous && config.api_keys.is_empty() {
+        warn!("anonymous access is denied and no api keys are configured, every request will be rejected");
+    }
+    Ok(router.layer(middleware::from_fn_with_state(
+        Arc::new(Access::try_from(config)?),
+        limit,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use super::{parse_api_key, Access, AccessConfig, ApiKey, Client, RateLimiter};
+
+    #[test]
+    fn api_keys() {
+        assert_eq!(
+            parse_api_key("discord-bot:s3cret"),
+            Ok(ApiKey {
+                name: "discord-bot".to_string(),
+                key: "s3cret".to_string(),
+                rate_limit: None
+            })
+        );
+        assert_eq!(
+            parse_api_key("trade-site:s3cret:1200").map(|key| key.rate_limit),
+            Ok(Some(1200))
+        );
+        assert!(parse_api_key("s3cret").is_err());
+        assert!(parse_api_key(":s3cret").is_err());
+        assert!(parse_api_key("bot:s3cret:many").is_err());
+    }
+
+    #[test]
+    fn identify() {
+        let config = AccessConfig {
+            api_keys: vec![
+                parse_api_key("bot:s3cret").expect("key"),
+                parse_api_key("site:other:5").expect("key"),
+            ],
+            ..AccessConfig::default()
+        };
+        let address = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        let access = Access::try_from(config.clone()).expect("unique keys");
+        let client = |key| access.identify(key, address).map_err(|err| err.to_string());
+        assert_eq!(client(Some("s3cret")), Ok((Client::Key("bot".into()), 600)));
+        assert_eq!(client(Some("other")), Ok((Client::Key("site".into()), 5)));
+        assert_eq!(client(None), Ok((Client::Address(address), 60)));
+        assert_eq!(client(Some("guess")), Err("invalid api key".to_string()));
+
+        let access = Access::try_from(AccessConfig {
+            deny_anonymous: true,
+            ..config
+        })
+        .expect("unique keys");
+        assert!(access.identify(None, address).is_err());
+        assert!(access.identify(Some("s3cret"), address).is_ok());
+    }
+
+    #[test]
+    fn unique_keys() {
+        let access = |keys: &[&str]| {
+            Access::try_from(AccessConfig {
+                api_keys: keys
+                    .iter()
+                    .map(|key| parse_api_key(key).expect("key"))
+                    .collect(),
+                ..AccessConfig::default()
+            })
+        };
+        assert!(access(&["bot:s3cret", "site:other"]).is_ok());
+        assert_eq!(
+            access(&["bot:s3cret", "bot:other:5"]).err(),
+            Some("api key name bot is used more than once".to_string())
+        );
+        assert_eq!(
+            access(&["bot:s3cret", "site:s3cret"]).err(),
+            Some("api key site has the key of another".to_string())
+        );
+    }
+
+    #[test]
+    fn fixed_windows() {
+        let limiter = RateLimiter::default();
+        let bot = Client::Key("bot".to_string());
+        let start = Instant::now();
+
+        let quota = limiter.acquire(&bot, 2, start).expect("first request");
+        assert_eq!((quota.remaining, quota.resets_in), (1, 60));
+        let quota = limiter
+            .acquire(&bot, 2, start + Duration::from_millis(10_500))
+            .expect("second request");
+        assert_eq!((quota.remaining, quota.resets_in), (0, 50));
+        let spent = limiter
+            .acquire(&bot, 2, start + Duration::from_secs(30))
+            .expect_err("over the limit");
+        assert_eq!((spent.remaining, spent.resets_in), (0, 30));
+
+        // a lower limit than already used leaves nothing, instead of underflowing
+        let spent = limiter
+            .acquire(&bot, 1, start + Duration::from_secs(30))
+            .expect_err("over the lower limit");
+        assert_eq!(spent.remaining, 0);
+
+        // other clients have their own windows
+        assert!(limiter.acquire(&Client::Address(None), 2, start).is_ok());
+
+        let quota = limiter
+            .acquire(&bot, 2, start + Duration::from_secs(60))
+            .expect("next window");
+        assert_eq!((quota.remaining, quota.resets_in), (1, 60));
+    }
+}
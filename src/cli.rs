@@ -0,0 +1,599 @@
+use async_graphql::EnumType;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use serde_json::json;
+use std::{error::Error, fmt::Debug, io::Write, path::PathBuf, time::Duration};
+
+use crate::access::AccessConfig;
+use crate::export::{export_lines, Dataset, ExportParams, Format};
+use crate::logging::LogFormat;
+use crate::rest::{graphql_enum, orderby, parse_input};
+use crate::schema::{
+    get_currencies, get_items, record, search, CacheFormat, ClientConfig, Currency,
+    CurrencyEndpoint, CurrencyOrderby, Item, ItemEndpoint, ItemOrderby, League, Limits, Orderby,
+    SearchHit, SearchKind, SearchResult, Upstream, POE_NINJA_URL,
+};
+
+/// poe.ninja prices from the command line, or served over graphql and rest
+#[derive(Debug, Parser)]
+#[command(name = "poe-api", version)]
+pub struct Cli {
+    /// league variant or alias: sc, hc, ruthless, hc-ruthless, std, hc-std
+    #[arg(long, short, global = true, default_value = "sc", value_parser = parse_league)]
+    pub league: League,
+    /// output of price and top
+    #[arg(long, short, global = true, value_enum, default_value_t = Output::Table)]
+    pub output: Output,
+    /// reads recorded responses from --data-dir instead of poe.ninja
+    #[arg(long, global = true)]
+    pub offline: bool,
+    /// where record writes responses and --offline reads them
+    #[arg(long, global = true, default_value = "data")]
+    pub data_dir: PathBuf,
+    /// poe.ninja, or a stand-in serving the same api
+    #[arg(long, global = true, default_value = POE_NINJA_URL)]
+    pub base_url: String,
+    /// seconds before a poe.ninja request is given up
+    #[arg(long, global = true, default_value_t = 30)]
+    pub timeout: u64,
+    /// times a failed poe.ninja request is retried, with exponential backoff
+    #[arg(long, global = true, default_value_t = 3)]
+    pub retries: u32,
+    /// poe.ninja requests in flight at once
+    #[arg(long, global = true, default_value_t = 4)]
+    pub concurrency: usize,
+    /// encoding of the cache files, zstd is smaller and faster to load
+    #[arg(long, global = true, value_enum, default_value_t = CacheFormat::Json)]
+    pub cache_format: CacheFormat,
+    /// of the log lines on stderr, filtered by `RUST_LOG`
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "POE_API_LOG_FORMAT",
+        default_value_t = LogFormat::Pretty
+    )]
+    pub log_format: LogFormat,
+    #[command(flatten)]
+    pub limits: Limits,
+    /// starts the server when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// fuzzy searches items and currencies by name
+    Price {
+        query: String,
+        #[arg(long, short = 'n', default_value_t = 5)]
+        limit: usize,
+    },
+    /// most valuable items or currencies, optionally of a single endpoint
+    Top {
+        /// item or currency endpoint, e.g. Scarab or Fragment
+        #[arg(long, short, value_parser = parse_endpoint)]
+        endpoint: Option<Endpoint>,
+        /// field to sort by, descending unless --asc is given
+        #[arg(long, default_value = "chaos_value")]
+        by: String,
+        #[arg(long)]
+        asc: bool,
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+    },
+    /// writes items or currencies to stdout
+    Export {
+        #[arg(long, short, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        #[arg(long, short, value_enum, default_value_t = Dataset::Items)]
+        dataset: Dataset,
+        /// json graphql where input, e.g. '{"name": {"icontains": "scarab"}}'
+        #[arg(long = "where")]
+        filter: Option<String>,
+        /// comma separated fields, prefixed with - for descending
+        #[arg(long)]
+        sort: Option<String>,
+        /// comma separated columns, * for all of them
+        #[arg(long)]
+        columns: Option<String>,
+        /// most rows written, at most --max-results
+        #[arg(long, short = 'n')]
+        limit: Option<usize>,
+        /// rows skipped, to page through exports larger than --max-results
+        #[arg(long)]
+        offset: Option<usize>,
+    },
+    /// records the poe.ninja responses of the league into --data-dir for --offline
+    Record,
+    /// starts the graphql and rest server
+    Serve {
+        #[arg(long, short, default_value_t = 3000)]
+        port: u16,
+        /// bearer token of the invalidateCache and refreshNow mutations,
+        /// which are disabled without one
+        #[arg(long, env = "POE_API_ADMIN_TOKEN", hide_env_values = true)]
+        admin_token: Option<String>,
+        /// comma separated leagues loaded on start, /readyz waits for them.
+        /// --league when omitted
+        #[arg(long, value_delimiter = ',', value_parser = parse_league)]
+        leagues: Vec<League>,
+        #[command(flatten)]
+        access: AccessConfig,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Endpoint {
+    Item(ItemEndpoint),
+    Currency(CurrencyEndpoint),
+}
+
+/// matches the variant or graphql name of an enum, ignoring case
+fn parse_enum<T: EnumType + Debug>(value: &str) -> Option<T> {
+    T::items()
+        .iter()
+        .find(|item| {
+            item.name.eq_ignore_ascii_case(value)
+                || format!("{:?}", item.value).eq_ignore_ascii_case(value)
+        })
+        .map(|item| item.value)
+}
+
+fn parse_league(value: &str) -> Result<League, String> {
+    let league = match value.to_lowercase().as_str() {
+        "sc" | "softcore" | "league" => League::TmpStandard,
+        "hc" => League::TmpHardcore,
+        "ruthless" => League::TmpRuthless,
+        "hc-ruthless" | "hcr" => League::TmpHardcoreRuthless,
+        "std" => League::Standard,
+        "hc-std" => League::Hardcore,
+        _ => return parse_enum(value).ok_or_else(|| format!("unknown league {value}")),
+    };
+    Ok(league)
+}
+
+fn parse_endpoint(value: &str) -> Result<Endpoint, String> {
+    parse_enum(value)
+        .map(Endpoint::Item)
+        .or_else(|| parse_enum(value).map(Endpoint::Currency))
+        .ok_or_else(|| format!("unknown endpoint {value}"))
+}
+
+/// a row of the price table, shared by items and currencies
+struct PriceRow {
+    name: String,
+    detail: String,
+    chaos: f64,
+    divine: f64,
+    change: f64,
+    listings: Option<i32>,
+}
+
+impl From<&Item> for PriceRow {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.name.clone(),
+            detail: item
+                .variant
+                .as_ref()
+                .or(item.base_type.as_ref())
+                .cloned()
+                .unwrap_or_default(),
+            chaos: item.chaos_value,
+            divine: item.divine_value,
+            change: item.sparkline.total_change,
+            listings: Some(item.listing_count),
+        }
+    }
+}
+
+impl From<&Currency> for PriceRow {
+    fn from(currency: &Currency) -> Self {
+        Self {
+            name: currency.name.clone(),
+            detail: String::new(),
+            chaos: currency.chaos_value,
+            divine: currency.divine_value,
+            change: currency.receive_spark_line.total_change,
+            listings: currency
+                .receive
+                .as_ref()
+                .map(|receive| receive.listing_count),
+        }
+    }
+}
+
+impl PriceRow {
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.detail.clone(),
+            format!("{:.1}", self.chaos),
+            format!("{:.2}", self.divine),
+            format!("{:+.1}%", self.change),
+            self.listings.map(|n| n.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+const PRICE_HEADERS: [&str; 6] = ["NAME", "DETAIL", "CHAOS", "DIVINE", "7D", "LISTINGS"];
+
+/// aligns the cells into columns, text columns left and the rest right aligned
+fn table(headers: &[&str], rows: &[Vec<String>], text_columns: usize) -> String {
+    let widths: Vec<_> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([header.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let line = |cells: Vec<&str>| {
+        let cells: Vec<_> = cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                if i < text_columns {
+                    format!("{cell:<width$}")
+                } else {
+                    format!("{cell:>width$}")
+                }
+            })
+            .collect();
+        format!("{}\n", cells.join("  ").trim_end())
+    };
+
+    std::iter::once(line(headers.to_vec()))
+        .chain(
+            rows.iter()
+                .map(|row| line(row.iter().map(String::as_str).collect())),
+        )
+        .collect()
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn price(
+    league: League,
+    output: Output,
+    query: &str,
+    limit: usize,
+) -> Result<(), Box<dyn Error>> {
+    let kinds = [SearchKind::Item, SearchKind::Currency];
+    let results: Vec<SearchResult> = search(query, league, limit, &kinds).await?;
+
+    if output == Output::Json {
+        return print_json(&results);
+    }
+
+    let rows: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let row = match &result.hit {
+                SearchHit::Item(item) => PriceRow::from(item),
+                SearchHit::Currency(currency) => PriceRow::from(currency),
+            };
+            let mut cells = row.cells();
+            cells.push(format!("{:.2}", result.score));
+            cells
+        })
+        .collect();
+    let mut headers = PRICE_HEADERS.to_vec();
+    headers.push("SCORE");
+    print!("{}", table(&headers, &rows, 2));
+
+    Ok(())
+}
+
+async fn top(
+    league: League,
+    output: Output,
+    endpoint: Option<Endpoint>,
+    by: &str,
+    asc: bool,
+    limit: usize,
+) -> Result<(), Box<dyn Error>> {
+    let sort = if asc {
+        by.to_string()
+    } else {
+        format!("-{by}")
+    };
+
+    let rows: Vec<PriceRow> = if let Some(Endpoint::Currency(endpoint)) = endpoint {
+        let filter = parse_input(json!({ "endpoint": { "eq": graphql_enum(&endpoint)? } }))?;
+        let orderby = orderby(Some(&sort), CurrencyOrderby::name(Orderby::Asc))?;
+        let mut currencies = Box::pin(get_currencies(Some(filter), orderby, Some(league))).await?;
+        currencies.truncate(limit);
+
+        if output == Output::Json {
+            return print_json(&currencies);
+        }
+        currencies.iter().map(PriceRow::from).collect()
+    } else {
+        let filter = match endpoint {
+            Some(Endpoint::Item(endpoint)) => Some(parse_input(
+                json!({ "endpoint": { "eq": graphql_enum(&endpoint)? } }),
+            )?),
+            _ => None,
+        };
+        let orderby = orderby(Some(&sort), ItemOrderby::name(Orderby::Asc))?;
+        let mut items = Box::pin(get_items(filter, orderby, Some(league))).await?;
+        items.truncate(limit);
+
+        if output == Output::Json {
+            return print_json(&items);
+        }
+        items.iter().map(PriceRow::from).collect()
+    };
+
+    let rows: Vec<_> = rows.iter().map(PriceRow::cells).collect();
+    print!("{}", table(&PRICE_HEADERS, &rows, 2));
+
+    Ok(())
+}
+
+async fn export(
+    dataset: Dataset,
+    format: Format,
+    params: ExportParams,
+) -> Result<(), Box<dyn Error>> {
+    let lines = export_lines(dataset, format, &params).await?;
+
+    let mut stdout = std::io::stdout().lock();
+    for line in lines {
+        stdout.write_all(line?.as_bytes())?;
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
+
+impl Cli {
+    pub fn upstream(&self) -> Upstream {
+        if self.offline {
+            Upstream::Offline(self.data_dir.clone())
+        } else {
+            Upstream::Live {
+                base_url: self.base_url.trim_end_matches('/').to_string(),
+            }
+        }
+    }
+
+    pub fn client_config(&self) -> ClientConfig {
+        ClientConfig {
+            timeout: Duration::from_secs(self.timeout),
+            retries: self.retries,
+            concurrency: self.concurrency,
+            ..ClientConfig::default()
+        }
+    }
+}
+
+/// runs every command except serve, which needs the router and errors here
+pub async fn run(cli: Cli, command: Command) -> Result<(), Box<dyn Error>> {
+    let Cli { league, output, .. } = cli;
+
+    match command {
+        Command::Price { query, limit } => price(league, output, &query, limit).await,
+        Command::Top {
+            endpoint,
+            by,
+            asc,
+            limit,
+        } => top(league, output, endpoint, &by, asc, limit).await,
+        Command::Export {
+            format,
+            dataset,
+            filter,
+            sort,
+            columns,
+            limit,
+            offset,
+        } => {
+            let params = ExportParams {
+                league: Some(league),
+                filter,
+                sort,
+                columns,
+                limit,
+                offset,
+            };
+            export(dataset, format, params).await
+        }
+        Command::Record => {
+            for path in record(&cli.data_dir, league).await? {
+                println!("{}", path.display());
+            }
+            Ok(())
+        }
+        Command::Serve { .. } => Err("serve is run by the poe-api binary, not cli::run".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use std::time::Duration;
+
+    use super::{parse_endpoint, parse_league, run, table, Cli, Command, Endpoint, Output};
+    use crate::access::{parse_api_key, AccessConfig};
+    use crate::logging::LogFormat;
+    use crate::schema::{ClientConfig, CurrencyEndpoint, ItemEndpoint, League, Limits, Upstream};
+
+    #[test]
+    fn league_aliases() {
+        assert_eq!(parse_league("hc"), Ok(League::TmpHardcore));
+        assert_eq!(parse_league("Standard"), Ok(League::Standard));
+        assert_eq!(parse_league("prev_hardcore"), Ok(League::PrevHardcore));
+        assert!(parse_league("wildwood").is_err());
+    }
+
+    #[test]
+    fn endpoints() {
+        assert!(matches!(
+            parse_endpoint("scarab"),
+            Ok(Endpoint::Item(ItemEndpoint::Scarab))
+        ));
+        assert!(matches!(
+            parse_endpoint("Fragment"),
+            Ok(Endpoint::Currency(CurrencyEndpoint::Fragment))
+        ));
+        assert!(parse_endpoint("Mageblood").is_err());
+    }
+
+    #[test]
+    fn subcommands() {
+        let cli = Cli::parse_from([
+            "poe-api",
+            "top",
+            "--endpoint",
+            "Scarab",
+            "--by",
+            "chaos_value",
+            "-n",
+            "20",
+            "-o",
+            "json",
+        ]);
+        assert_eq!(cli.output, Output::Json);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Top {
+                endpoint: Some(Endpoint::Item(ItemEndpoint::Scarab)),
+                limit: 20,
+                asc: false,
+                ..
+            })
+        ));
+
+        let cli = Cli::parse_from(["poe-api", "price", "Mageblood", "--league", "hc"]);
+        assert_eq!(cli.league, League::TmpHardcore);
+        assert_eq!(cli.log_format, LogFormat::Pretty);
+
+        let cli = Cli::parse_from(["poe-api", "serve", "--log-format", "json"]);
+        assert_eq!(cli.log_format, LogFormat::Json);
+
+        assert!(Cli::parse_from(["poe-api"]).command.is_none());
+
+        let cli = Cli::parse_from(["poe-api", "serve", "--admin-token", "secret"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve { port: 3000, admin_token: Some(token), .. }) if token == "secret"
+        ));
+
+        let limits = Limits {
+            results: 100,
+            ..Limits::default()
+        };
+        let cli = Cli::parse_from(["poe-api", "serve", "--max-results", "100"]);
+        assert_eq!(cli.limits, limits);
+        let cli = Cli::parse_from(["poe-api", "--max-results", "100"]);
+        assert_eq!(cli.limits, limits);
+        assert_eq!(
+            Cli::parse_from(["poe-api", "top"]).limits,
+            Limits::default()
+        );
+
+        let cli = Cli::parse_from([
+            "poe-api",
+            "serve",
+            "--api-key",
+            "bot:s3cret",
+            "--api-key",
+            "site:other:1200",
+            "--ip-rate-limit",
+            "10",
+            "--deny-anonymous",
+        ]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve { access, .. }) if access == AccessConfig {
+                api_keys: vec![
+                    parse_api_key("bot:s3cret").expect("key"),
+                    parse_api_key("site:other:1200").expect("key"),
+                ],
+                ip_rate_limit: 10,
+                deny_anonymous: true,
+                ..AccessConfig::default()
+            }
+        ));
+
+        let cli = Cli::parse_from(["poe-api", "serve", "--leagues", "sc,hc-std"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve { leagues, .. })
+                if leagues == [League::TmpStandard, League::Hardcore]
+        ));
+    }
+
+    #[test]
+    fn offline_upstream() {
+        let cli = Cli::parse_from([
+            "poe-api",
+            "price",
+            "Mageblood",
+            "--offline",
+            "--data-dir",
+            "fixtures",
+        ]);
+        assert_eq!(cli.upstream(), Upstream::Offline("fixtures".into()));
+
+        let cli = Cli::parse_from(["poe-api", "record", "--data-dir", "fixtures"]);
+        assert_eq!(cli.upstream(), Upstream::default());
+        assert!(matches!(cli.command, Some(Command::Record)));
+
+        let cli = Cli::parse_from(["poe-api", "serve", "--base-url", "http://127.0.0.1:8080/"]);
+        assert!(matches!(
+            cli.upstream(),
+            Upstream::Live { base_url } if base_url == "http://127.0.0.1:8080"
+        ));
+    }
+
+    #[tokio::test]
+    async fn serve_is_not_run() {
+        let mut cli = Cli::parse_from(["poe-api", "serve"]);
+        let command = cli.command.take().expect("serve");
+        assert!(run(cli, command).await.is_err());
+    }
+
+    #[test]
+    fn client_config() {
+        assert_eq!(
+            Cli::parse_from(["poe-api"]).client_config(),
+            ClientConfig::default()
+        );
+
+        let cli = Cli::parse_from(["poe-api", "--timeout", "5", "--retries", "0"]);
+        let config = cli.client_config();
+        assert_eq!(config.timeout, Duration::from_secs(5));
+        assert_eq!(config.retries, 0);
+    }
+
+    #[test]
+    fn aligned_table() {
+        let rows = vec![
+            vec!["Mageblood".to_string(), "250000.0".to_string()],
+            vec!["Headhunter".to_string(), "9000.0".to_string()],
+        ];
+        assert_eq!(
+            table(&["NAME", "CHAOS"], &rows, 1),
+            "NAME           CHAOS\nMageblood   250000.0\nHeadhunter    9000.0\n"
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::rest::{camel_case, orderby, parse_input, ApiError};
+use crate::schema::{
+    check_where, get_currencies, get_items, page, CurrencyOrderby, CurrencyWhere, ItemOrderby,
+    ItemWhere, League, Orderby,
+};
+
+const ITEM_COLUMNS: &[&str] = &[
+    "id",
+    "name",
+    "baseType",
+    "variant",
+    "chaosValue",
+    "divineValue",
+    "listingCount",
+    "sparkline.totalChange",
+    "endpoint",
+];
+
+const CURRENCY_COLUMNS: &[&str] = &[
+    "id",
+    "name",
+    "chaosEquivalent",
+    "divineValue",
+    "receiveSparkLine.totalChange",
+    "endpoint",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Dataset {
+    Items,
+    Currency,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Ndjson,
+}
+
+impl Format {
+    const fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Tsv => "text/tab-separated-values; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// the header line, ndjson has none
+    fn header(self, columns: &[String]) -> Option<String> {
+        match self {
+            Self::Csv => Some(csv_line(columns)),
+            Self::Tsv => Some(tsv_line(columns)),
+            Self::Ndjson => None,
+        }
+    }
+
+    fn line(self, columns: &[String], row: &Map<String, Value>) -> String {
+        let value = |column: &String| row.get(column).unwrap_or(&Value::Null);
+        let cells = || -> Vec<_> { columns.iter().map(|column| cell(value(column))).collect() };
+
+        match self {
+            Self::Csv => csv_line(&cells()),
+            Self::Tsv => tsv_line(&cells()),
+            Self::Ndjson => {
+                let object: Map<_, _> = columns
+                    .iter()
+                    .map(|column| (column.clone(), value(column).clone()))
+                    .collect();
+                format!("{}\n", Value::Object(object))
+            }
+        }
+    }
+}
+
+/// `items.csv` => (Items, Csv)
+fn parse_file(file: &str) -> Result<(Dataset, Format), ApiError> {
+    let (dataset, format) = file
+        .rsplit_once('.')
+        .ok_or_else(|| ApiError::not_found(format!("unknown export {file}")))?;
+
+    let dataset = match dataset {
+        "items" => Dataset::Items,
+        "currency" => Dataset::Currency,
+        _ => return Err(ApiError::not_found(format!("unknown dataset {dataset}"))),
+    };
+    let format = match format {
+        "csv" => Format::Csv,
+        "tsv" => Format::Tsv,
+        "ndjson" => Format::Ndjson,
+        _ => return Err(ApiError::not_found(format!("unknown format {format}"))),
+    };
+
+    Ok((dataset, format))
+}
+
+fn csv_line<S: AsRef<str>>(cells: &[S]) -> String {
+    let cells: Vec<_> = cells
+        .iter()
+        .map(AsRef::as_ref)
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect();
+    format!("{}\n", cells.join(","))
+}
+
+/// tsv has no quoting, so tabs and newlines inside cells become spaces
+fn tsv_line<S: AsRef<str>>(cells: &[S]) -> String {
+    let cells: Vec<_> = cells
+        .iter()
+        .map(|cell| cell.as_ref().replace(['\t', '\n', '\r'], " "))
+        .collect();
+    format!("{}\n", cells.join("\t"))
+}
+
+/// text of a flattened value, nulls are empty
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// flattens a serialized row into dotted columns, e.g. `sparkline.totalChange`.
+/// lists are joined with `|`, modifiers by their text, e.g. `+1 to Level|10% increased Damage`
+fn flatten(prefix: &str, value: Value, row: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&key, value, row);
+            }
+        }
+        Value::Array(values) => {
+            let entries: Vec<_> = values
+                .iter()
+                .map(|value| match value {
+                    Value::Object(map) => map.get("text").map(cell).unwrap_or_default(),
+                    value => cell(value),
+                })
+                .collect();
+            row.insert(prefix.to_string(), Value::String(entries.join("|")));
+        }
+        value => {
+            row.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+/// a row flattened as described in [`flatten`]
+fn flatten_row<T: Serialize>(row: &T) -> Result<Map<String, Value>, ApiError> {
+    let value = serde_json::to_value(row)
+        .map_err(|err| ApiError::internal(format!("could not serialize a row: {err}")))?;
+    let mut flat = Map::new();
+    flatten("", value, &mut flat);
+    Ok(flat)
+}
+
+/// requested columns with `snake_case` segments converted, `*` selects every column
+fn columns<T: Serialize>(
+    requested: Option<&str>,
+    default: &[&str],
+    data: &[T],
+) -> Result<Vec<String>, ApiError> {
+    let Some(requested) = requested else {
+        return Ok(default.iter().map(ToString::to_string).collect());
+    };
+    if requested.trim() == "*" {
+        let first = data.first().map(flatten_row).transpose()?;
+        return Ok(first
+            .map(|row| row.keys().cloned().collect())
+            .unwrap_or_default());
+    }
+
+    let columns: Vec<String> = requested
+        .split(',')
+        .map(str::trim)
+        .filter(|column| !column.is_empty())
+        .map(|column| {
+            column
+                .split('.')
+                .map(camel_case)
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect();
+
+    // a column may be missing from some rows when a nested value is null,
+    // so rows are flattened until every column was seen
+    let mut unseen: Vec<_> = columns.iter().collect();
+    for row in data {
+        if unseen.is_empty() {
+            break;
+        }
+        let row = flatten_row(row)?;
+        unseen.retain(|column| !row.contains_key(*column));
+    }
+    if let Some(unknown) = unseen.first().filter(|_| !data.is_empty()) {
+        return Err(ApiError::bad_request(format!("unknown column {unknown}")));
+    }
+
+    Ok(columns)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportParams {
+    pub league: Option<League>,
+    /// json graphql where input, e.g. `{"name": {"icontains": "scarab"}}`
+    #[serde(rename = "where")]
+    pub filter: Option<String>,
+    pub sort: Option<String>,
+    pub columns: Option<String>,
+    /// rows are capped at the result limit, larger exports are paged with limit and offset
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+fn where_param<T: async_graphql::InputType>(filter: Option<&str>) -> Result<Option<T>, ApiError> {
+    filter
+        .map(|filter| {
+            let value: Value = serde_json::from_str(filter)
+                .map_err(|err| ApiError::bad_request(format!("invalid where: {err}")))?;
+            let graphql_value = async_graphql::Value::from_json(value.clone())
+                .map_err(|err| ApiError::bad_request(format!("invalid where: {err}")))?;
+            check_where(&graphql_value)?;
+            parse_input(value)
+        })
+        .transpose()
+}
+
+/// lines of an export, errors when a row can not be serialized
+pub type Lines = Box<dyn Iterator<Item = Result<String, ApiError>> + Send>;
+
+/// the header and a line per row, each row is flattened when its line is taken
+fn lines<T: Serialize + Send + 'static>(
+    data: Vec<T>,
+    format: Format,
+    requested: Option<&str>,
+    default: &[&str],
+) -> Result<Lines, ApiError> {
+    let columns = columns(requested, default, &data)?;
+    // rows of a type serialize alike, so a failing row fails the request before
+    // the response starts instead of cutting the stream short
+    if let Some(row) = data.first() {
+        flatten_row(row)?;
+    }
+    let header = format.header(&columns).map(Ok);
+    let lines = data
+        .into_iter()
+        .map(move |row| Ok(format.line(&columns, &flatten_row(&row)?)));
+    Ok(Box::new(header.into_iter().chain(lines)))
+}
+
+/// the lines of an export including the header, rows are flattened as described in [`flatten`]
+pub async fn export_lines(
+    dataset: Dataset,
+    format: Format,
+    params: &ExportParams,
+) -> Result<Lines, ApiError> {
+    let league = params.league.unwrap_or_default();
+    let sort = params.sort.as_deref();
+    let requested = params.columns.as_deref();
+
+    match dataset {
+        Dataset::Items => {
+            let filter = where_param::<ItemWhere>(params.filter.as_deref())?;
+            let orderby = orderby(sort, ItemOrderby::name(Orderby::Asc))?;
+            let items = Box::pin(get_items(filter, orderby, Some(league))).await?;
+            let items = page(items, params.offset, params.limit)?;
+            lines(items, format, requested, ITEM_COLUMNS)
+        }
+        Dataset::Currency => {
+            let filter = where_param::<CurrencyWhere>(params.filter.as_deref())?;
+            let orderby = orderby(sort, CurrencyOrderby::name(Orderby::Asc))?;
+            let currencies = Box::pin(get_currencies(filter, orderby, Some(league))).await?;
+            let currencies = page(currencies, params.offset, params.limit)?;
+            lines(currencies, format, requested, CURRENCY_COLUMNS)
+        }
+    }
+}
+
+/// `/export/{items,currency}.{csv,tsv,ndjson}`
+async fn export(
+    Path(file): Path<String>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, ApiError> {
+    let (dataset, format) = parse_file(&file)?;
+    let lines = export_lines(dataset, format, &params).await?;
+    let body = futures::stream::iter(lines);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file}\""),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+pub fn router() -> Router {
+    Router::new().route("/:file", get(export))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use super::{columns, csv_line, flatten_row, lines, parse_file, Dataset, Format, ITEM_COLUMNS};
+    use crate::schema::Item;
+
+    fn item() -> Item {
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "Headhunter",
+            "baseType": "Leather Belt",
+            "itemClass": 3,
+            "sparkline": {"data": [0, null, 2.5], "totalChange": 2.5},
+            "lowConfidenceSparkline": {"data": [], "totalChange": 0},
+            "implicitModifiers": [{"text": "+(25-40) to maximum Life", "optional": false}],
+            "explicitModifiers": [
+                {"text": "+(40-55) to Strength", "optional": false},
+                {"text": "When you Kill a Rare monster, you gain its Modifiers", "optional": false},
+            ],
+            "chaosValue": 1000.0,
+            "exaltedValue": 0,
+            "divineValue": 5.0,
+            "count": 1,
+            "detailsId": "headhunter-leather-belt",
+            "listingCount": 40,
+        }))
+        .expect("valid item")
+    }
+
+    fn row() -> Map<String, Value> {
+        flatten_row(&item()).expect("serializable")
+    }
+
+    #[test]
+    fn flattens_nested_values_and_lists() {
+        let row = row();
+        assert_eq!(row["sparkline.totalChange"], json!(2.5));
+        assert_eq!(row["sparkline.data"], json!("0.0||2.5"));
+        assert_eq!(
+            row["explicitModifiers"],
+            json!("+(40-55) to Strength|When you Kill a Rare monster, you gain its Modifiers")
+        );
+        assert_eq!(row["variant"], Value::Null);
+    }
+
+    #[test]
+    fn csv_quotes_cells() {
+        let row = row();
+        let columns = columns(
+            Some("name,explicit_modifiers,variant"),
+            ITEM_COLUMNS,
+            &[item()],
+        )
+        .expect("known columns");
+        assert_eq!(
+            Format::Csv.line(&columns, &row),
+            "Headhunter,\"+(40-55) to Strength|When you Kill a Rare monster, you gain its Modifiers\",\n"
+        );
+        assert_eq!(csv_line(&["a\"b"]), "\"a\"\"b\"\n");
+    }
+
+    #[test]
+    fn tsv_and_ndjson_lines() {
+        let row = row();
+        let columns = vec!["name".to_string(), "sparkline.totalChange".to_string()];
+
+        assert_eq!(
+            Format::Tsv.header(&columns).as_deref(),
+            Some("name\tsparkline.totalChange\n")
+        );
+        assert_eq!(Format::Tsv.line(&columns, &row), "Headhunter\t2.5\n");
+        assert_eq!(Format::Ndjson.header(&columns), None);
+        assert_eq!(
+            Format::Ndjson.line(&columns, &row),
+            "{\"name\":\"Headhunter\",\"sparkline.totalChange\":2.5}\n"
+        );
+    }
+
+    #[test]
+    fn column_selection() {
+        let rows = [item()];
+        assert_eq!(
+            columns(None, ITEM_COLUMNS, &rows).expect("default").len(),
+            ITEM_COLUMNS.len()
+        );
+        assert_eq!(
+            columns(
+                Some("chaos_value, sparkline.total_change"),
+                ITEM_COLUMNS,
+                &rows
+            )
+            .expect("known"),
+            ["chaosValue", "sparkline.totalChange"]
+        );
+        assert!(columns(Some("*"), ITEM_COLUMNS, &rows)
+            .expect("all")
+            .contains(&"detailsId".to_string()));
+        assert!(columns(Some("price"), ITEM_COLUMNS, &rows).is_err());
+    }
+
+    #[test]
+    fn lines_of_rows() {
+        let csv: Result<Vec<_>, _> = lines(
+            vec![item(), item()],
+            Format::Csv,
+            Some("name,chaos_value"),
+            ITEM_COLUMNS,
+        )
+        .expect("known columns")
+        .collect();
+        assert_eq!(
+            csv.expect("serializable"),
+            [
+                "name,chaosValue\n",
+                "Headhunter,1000.0\n",
+                "Headhunter,1000.0\n"
+            ]
+        );
+        let empty: Vec<_> = lines(Vec::<Item>::new(), Format::Ndjson, None, ITEM_COLUMNS)
+            .expect("default columns")
+            .collect();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn file_names() {
+        assert!(matches!(
+            parse_file("items.csv"),
+            Ok((Dataset::Items, Format::Csv))
+        ));
+        assert!(matches!(
+            parse_file("currency.ndjson"),
+            Ok((Dataset::Currency, Format::Ndjson))
+        ));
+        assert!(parse_file("items.xlsx").is_err());
+        assert!(parse_file("items").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::schema::{
+    is_loaded, load, render_metrics, timestamp, upstream_health, CacheKind, League,
+    METRICS_CONTENT_TYPE,
+};
+
+const KINDS: [CacheKind; 2] = [CacheKind::Item, CacheKind::Currency];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Readiness {
+    ready: bool,
+    /// configured leagues and kinds that were not loaded yet
+    loading: Vec<String>,
+    /// `None` until poe.ninja is contacted
+    upstream_reachable: Option<bool>,
+    last_upstream_success: Option<u64>,
+    last_upstream_failure: Option<u64>,
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// 503 until the data of the configured leagues is loaded, or when poe.ninja did not
+/// answer any request lately
+async fn readyz(State(leagues): State<Arc<[League]>>) -> impl IntoResponse {
+    let loading: Vec<_> = leagues
+        .iter()
+        .flat_map(|&league| KINDS.map(|kind| (league, kind)))
+        .filter(|&(league, kind)| !is_loaded(league, kind))
+        .map(|(league, kind)| format!("{league:?} {kind:?}"))
+        .collect();
+    let upstream = upstream_health();
+    let upstream_reachable = upstream.reachable(timestamp());
+
+    let readiness = Readiness {
+        ready: loading.is_empty() && upstream_reachable != Some(false),
+        loading,
+        upstream_reachable,
+        last_upstream_success: upstream.last_success,
+        last_upstream_failure: upstream.last_failure,
+    };
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+async fn metrics() -> impl IntoResponse {
+    ([(CONTENT_TYPE, METRICS_CONTENT_TYPE)], render_metrics())
+}
+
+/// loads the data of the leagues, so the server is ready before the first request
+pub async fn warm(leagues: &[League]) {
+    for &league in leagues {
+        for kind in KINDS {
+            if let Err(err) = load(league, kind).await {
+                warn!(?league, ?kind, error = %err, "could not load on start");
+            }
+        }
+    }
+}
+
+/// `/healthz` answers while the process is alive, `/readyz` once the leagues are loaded
+/// and `/metrics` in the prometheus text format
+pub fn router(leagues: Vec<League>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .with_state(leagues.into())
+}
@@ -0,0 +1,84 @@
+use async_graphql::http::GraphiQLSource;
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{
+    extract::{Extension, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    response::{self, IntoResponse},
+    routing::get,
+    Router,
+};
+use std::time::Instant;
+use tracing::info;
+
+pub mod access;
+pub mod cli;
+pub mod export;
+pub mod health;
+pub mod logging;
+pub mod rest;
+pub mod schema;
+
+use access::AccessConfig;
+use schema::{build_schema, ApiSchema, BearerToken, League, Quota};
+
+/// what the server is started with
+#[derive(Debug, Clone, Default)]
+pub struct AppConfig {
+    /// enables the admin mutations
+    pub admin_token: Option<String>,
+    /// leagues that are loaded on start and that `/readyz` waits for
+    pub leagues: Vec<League>,
+    /// api keys, rate limits and whether anonymous requests are served
+    pub access: AccessConfig,
+}
+
+async fn graphiql() -> impl IntoResponse {
+    response::Html(GraphiQLSource::build().endpoint("/").finish())
+}
+
+/// `Authorization: Bearer <token>`
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+async fn graphql(
+    State(schema): State<ApiSchema>,
+    headers: HeaderMap,
+    quota: Option<Extension<Quota>>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let mut request = request.into_inner();
+    if let Some(token) = bearer_token(&headers) {
+        request = request.data(BearerToken(token));
+    }
+    if let Some(Extension(quota)) = quota {
+        request = request.data(quota);
+    }
+    let operation = request.operation_name.clone();
+
+    let start = Instant::now();
+    let response = schema.execute(request).await;
+    info!(
+        operation,
+        errors = response.errors.len(),
+        duration_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        "graphql operation"
+    );
+    response.into()
+}
+
+/// graphql at `/`, rest at `/api`, exports at `/export` and the health checks and
+/// metrics at the root. the admin mutations are only enabled with an admin token.
+///
+/// api keys and rate limits apply to everything but the health checks and metrics.
+/// fails when two api keys share a name or a key
+pub fn app(config: AppConfig) -> Result<Router, String> {
+    let api = Router::new()
+        .route("/", get(graphiql).post(graphql))
+        .with_state(build_schema(config.admin_token))
+        .nest("/api", rest::router())
+        .nest("/export", export::router());
+    Ok(access::protect(api, config.access)?.merge(health::router(config.leagues)))
+}
@@ -0,0 +1,25 @@
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+/// how log lines are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// human readable, with the fields of every span on their own lines
+    Pretty,
+    /// an object per line, for log collectors
+    Json,
+}
+
+/// logs to stderr at `default_level` and above, unless `RUST_LOG` filters otherwise
+pub fn init_logging(format: LogFormat, default_level: &str) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match format {
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
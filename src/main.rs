@@ -1,31 +1,113 @@
-use async_graphql::{http::GraphiQLSource, EmptyMutation, EmptySubscription, Schema};
-use async_graphql_axum::GraphQL;
-use axum::{
-    response::{self, IntoResponse},
-    routing::get,
-    Router, Server,
-};
+use clap::Parser;
+use std::{error::Error, net::SocketAddr};
+use tokio::net::TcpListener;
+use tracing::info;
 
-mod schema;
+use poe_api::cli::{self, Cli, Command};
+use poe_api::{health, logging, schema, AppConfig};
 
-use schema::QueryRoot;
+/// resolves on ctrl-c, or on SIGTERM on unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("ctrl-c handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-async fn graphiql() -> impl IntoResponse {
-    response::Html(GraphiQLSource::build().endpoint("/").finish())
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+    info!("shutting down, draining requests in flight");
+}
+
+/// serves until ctrl-c or SIGTERM, then stops accepting connections and waits for
+/// the requests in flight
+async fn serve(port: u16, config: AppConfig) -> Result<(), Box<dyn Error>> {
+    let leagues = config.leagues.clone();
+    // the peer address is what anonymous requests are rate limited by
+    let app = poe_api::app(config)?.into_make_service_with_connect_info::<SocketAddr>();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|err| format!("could not listen on {addr}: {err}"))?;
+
+    let warm = tokio::spawn(async move { health::warm(&leagues).await });
+
+    info!("GraphiQL IDE: http://{addr}");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .map_err(|err| format!("server stopped: {err}"))?;
+
+    // cache files are written in one go after the data is fetched, so loading can be
+    // dropped at any await without leaving a partial file behind
+    warm.abort();
+    if let Err(err) = warm.await {
+        if !err.is_cancelled() {
+            return Err(format!("loading on start failed: {err}").into());
+        }
+    }
+    info!("stopped");
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish();
+    let mut cli = Cli::parse();
+    // commands print their results, only the server logs what it does
+    let serving = matches!(cli.command, None | Some(Command::Serve { .. }));
+    logging::init_logging(cli.log_format, if serving { "info" } else { "warn" });
 
-    let app = Router::new().route("/", get(graphiql).post_service(GraphQL::new(schema)));
+    schema::set_upstream(cli.upstream());
+    schema::set_cache_format(cli.cache_format);
+    schema::set_limits(cli.limits);
+    if let Err(err) = schema::set_client_config(cli.client_config()) {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
 
-    let port = 3000;
+    let result = match cli.command.take() {
+        None => {
+            let config = AppConfig {
+                admin_token: std::env::var("POE_API_ADMIN_TOKEN").ok(),
+                leagues: vec![cli.league],
+                ..AppConfig::default()
+            };
+            serve(3000, config).await
+        }
+        Some(Command::Serve {
+            port,
+            admin_token,
+            mut leagues,
+            access,
+        }) => {
+            if leagues.is_empty() {
+                leagues.push(cli.league);
+            }
+            serve(
+                port,
+                AppConfig {
+                    admin_token,
+                    leagues,
+                    access,
+                },
+            )
+            .await
+        }
+        Some(command) => cli::run(cli, command).await,
+    };
 
-    println!("GraphiQL IDE: http://localhost:{}", port);
-
-    Server::bind(&format!("127.0.0.1:{}", port).parse().unwrap())
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,471 @@
+use async_graphql::{EnumType, InputType, Pos};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::schema::{
+    get_currencies, get_items, page, Currency, CurrencyEndpoint, CurrencyOrderby, CurrencyWhere,
+    FetchError, Item, ItemEndpoint, ItemOrderby, ItemWhere, League, LimitError, Orderby,
+};
+
+/// json error body, `{"error": "..."}`
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: message.into(),
+        }
+    }
+
+    pub fn too_many_requests(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: message.into(),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// poe.ninja could not be reached or answered with garbage
+impl From<FetchError> for ApiError {
+    fn from(err: FetchError) -> Self {
+        Self {
+            status: StatusCode::BAD_GATEWAY,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// the query asks for more than the limits allow
+impl From<LimitError> for ApiError {
+    fn from(err: LimitError) -> Self {
+        Self::bad_request(err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CurrencyParams {
+    endpoint: Option<CurrencyEndpoint>,
+    /// case insensitive substring of the name
+    name: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ItemParams {
+    endpoint: Option<ItemEndpoint>,
+    /// case insensitive substring of the name
+    name: Option<String>,
+    min_chaos: Option<f64>,
+    max_chaos: Option<f64>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// parses a graphql input from json, so query params go through the same validation as graphql
+pub fn parse_input<T: InputType>(value: Value) -> Result<T, ApiError> {
+    let value = async_graphql::Value::from_json(value)
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+    T::parse(Some(value))
+        .map_err(|err| ApiError::bad_request(err.into_server_error(Pos::default()).message))
+}
+
+/// graphql name of an enum value, e.g. `DivinationCard` => `DIVINATION_CARD`
+pub fn graphql_enum<T: InputType>(value: &T) -> Result<Value, ApiError> {
+    value
+        .to_value()
+        .into_json()
+        .map_err(|err| ApiError::bad_request(err.to_string()))
+}
+
+pub fn camel_case(field: &str) -> String {
+    let mut words = field.split('_');
+    let first = words.next().unwrap_or_default().to_string();
+    words.fold(first, |mut acc, word| {
+        let mut chars = word.chars();
+        if let Some(c) = chars.next() {
+            acc.extend(c.to_uppercase());
+            acc.push_str(chars.as_str());
+        }
+        acc
+    })
+}
+
+/// `-chaos_value,name,sparkline.total_change` => `[{"chaosValue": "DESC"}, {"name": "ASC"}, ...]`
+fn sort_value(sort: &str) -> Value {
+    sort.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (direction, path) = match field.strip_prefix('-') {
+                Some(path) => ("DESC", path),
+                None => ("ASC", field.trim_start_matches('+')),
+            };
+            path.rsplit('.').fold(
+                json!(direction),
+                |value, key| json!({ camel_case(key): value }),
+            )
+        })
+        .collect()
+}
+
+pub fn orderby<T: InputType>(sort: Option<&str>, default: T) -> Result<Vec<T>, ApiError> {
+    match sort {
+        Some(sort) => parse_input(sort_value(sort)),
+        None => Ok(vec![default]),
+    }
+}
+
+/// parses the collected filters, None if there are none
+fn where_input<T: InputType>(filters: Map<String, Value>) -> Result<Option<T>, ApiError> {
+    if filters.is_empty() {
+        return Ok(None);
+    }
+    parse_input(Value::Object(filters)).map(Some)
+}
+
+fn currency_where(params: &CurrencyParams) -> Result<Option<CurrencyWhere>, ApiError> {
+    let mut filters = Map::new();
+    if let Some(endpoint) = &params.endpoint {
+        filters.insert("endpoint".into(), json!({ "eq": graphql_enum(endpoint)? }));
+    }
+    if let Some(name) = &params.name {
+        filters.insert("name".into(), json!({ "icontains": name }));
+    }
+    where_input(filters)
+}
+
+fn item_where(params: &ItemParams) -> Result<Option<ItemWhere>, ApiError> {
+    let mut filters = Map::new();
+    if let Some(endpoint) = &params.endpoint {
+        filters.insert("endpoint".into(), json!({ "eq": graphql_enum(endpoint)? }));
+    }
+    if let Some(name) = &params.name {
+        filters.insert("name".into(), json!({ "icontains": name }));
+    }
+
+    let mut chaos_value = Map::new();
+    if let Some(min) = params.min_chaos {
+        chaos_value.insert("gte".into(), json!(min));
+    }
+    if let Some(max) = params.max_chaos {
+        chaos_value.insert("lte".into(), json!(max));
+    }
+    if !chaos_value.is_empty() {
+        filters.insert("chaosValue".into(), Value::Object(chaos_value));
+    }
+
+    where_input(filters)
+}
+
+async fn currencies(
+    Path(league): Path<League>,
+    Query(params): Query<CurrencyParams>,
+) -> Result<Json<Vec<Currency>>, ApiError> {
+    let filter = currency_where(&params)?;
+    let orderby = orderby(params.sort.as_deref(), CurrencyOrderby::name(Orderby::Asc))?;
+
+    let currencies = Box::pin(get_currencies(filter, orderby, Some(league))).await?;
+
+    Ok(Json(page(currencies, params.offset, params.limit)?))
+}
+
+async fn items(
+    Path(league): Path<League>,
+    Query(params): Query<ItemParams>,
+) -> Result<Json<Vec<Item>>, ApiError> {
+    let filter = item_where(&params)?;
+    let orderby = orderby(params.sort.as_deref(), ItemOrderby::name(Orderby::Asc))?;
+
+    let items = Box::pin(get_items(filter, orderby, Some(league))).await?;
+
+    Ok(Json(page(items, params.offset, params.limit)?))
+}
+
+async fn item(Path((league, details_id)): Path<(League, String)>) -> Result<Json<Item>, ApiError> {
+    Box::pin(get_items(None, vec![], Some(league)))
+        .await?
+        .into_iter()
+        .find(|item| item.details_id == details_id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("no item with details id {details_id}")))
+}
+
+/// serde names of the enum, as accepted in paths and query params
+fn enum_values<T: EnumType + Serialize>() -> Vec<Value> {
+    T::items()
+        .iter()
+        .filter_map(|item| serde_json::to_value(item.value).ok())
+        .collect()
+}
+
+fn json_ref(schema: &str) -> Value {
+    json!({ "application/json": { "schema": { "$ref": format!("#/components/schemas/{schema}") } } })
+}
+
+fn query_param(name: &str, schema: &Value, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "schema": schema,
+        "description": description,
+    })
+}
+
+// openapi path templates use braces, they are not format arguments
+#[allow(clippy::literal_string_with_formatting_args)]
+fn openapi_paths() -> Value {
+    let league = json!({
+        "name": "league",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "enum": enum_values::<League>() },
+    });
+    let name = query_param(
+        "name",
+        &json!({ "type": "string" }),
+        "case insensitive substring of the name",
+    );
+    let sort = query_param(
+        "sort",
+        &json!({ "type": "string" }),
+        "comma separated fields, prefixed with - for descending, e.g. -chaos_value,name",
+    );
+    let limit = query_param("limit", &json!({ "type": "integer", "minimum": 0 }), "");
+    let offset = query_param("offset", &json!({ "type": "integer", "minimum": 0 }), "");
+    let error = json!({ "description": "invalid parameters", "content": json_ref("Error") });
+    let array_of = |schema: &str| {
+        json!({
+            "description": "ok",
+            "content": { "application/json": { "schema": {
+                "type": "array",
+                "items": { "$ref": format!("#/components/schemas/{schema}") },
+            } } },
+        })
+    };
+    let endpoint =
+        |values| query_param("endpoint", &json!({ "type": "string", "enum": values }), "");
+
+    json!({
+        "/api/{league}/currency": { "get": {
+            "summary": "currencies of the league",
+            "parameters": [
+                league,
+                endpoint(enum_values::<CurrencyEndpoint>()),
+                name,
+                sort,
+                limit,
+                offset,
+            ],
+            "responses": { "200": array_of("Currency"), "400": error },
+        } },
+        "/api/{league}/items": { "get": {
+            "summary": "items of the league",
+            "parameters": [
+                league,
+                endpoint(enum_values::<ItemEndpoint>()),
+                name,
+                query_param("min_chaos", &json!({ "type": "number" }), "minimum price in chaos orbs"),
+                query_param("max_chaos", &json!({ "type": "number" }), "maximum price in chaos orbs"),
+                sort,
+                limit,
+                offset,
+            ],
+            "responses": { "200": array_of("Item"), "400": error },
+        } },
+        "/api/{league}/items/{detailsId}": { "get": {
+            "summary": "a single item by its poe.ninja details id",
+            "parameters": [
+                league,
+                { "name": "detailsId", "in": "path", "required": true, "schema": { "type": "string" } },
+            ],
+            "responses": {
+                "200": { "description": "ok", "content": json_ref("Item") },
+                "404": { "description": "no such item", "content": json_ref("Error") },
+            },
+        } },
+    })
+}
+
+fn openapi_schemas() -> Value {
+    json!({
+        "Error": {
+            "type": "object",
+            "properties": { "error": { "type": "string" } },
+        },
+        "Currency": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "name": { "type": "string" },
+                "chaosEquivalent": { "type": "number" },
+                "divineValue": { "type": "number" },
+                "endpoint": { "type": "string" },
+            },
+            "additionalProperties": true,
+        },
+        "Item": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string" },
+                "baseType": { "type": "string", "nullable": true },
+                "variant": { "type": "string", "nullable": true },
+                "chaosValue": { "type": "number" },
+                "divineValue": { "type": "number" },
+                "listingCount": { "type": "integer" },
+                "detailsId": { "type": "string" },
+                "endpoint": { "type": "string" },
+            },
+            "additionalProperties": true,
+        },
+    })
+}
+
+async fn openapi() -> Json<Value> {
+    Json(json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "poe-api",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "poe.ninja prices, also available through graphql at /",
+        },
+        "paths": openapi_paths(),
+        "components": { "schemas": openapi_schemas() },
+    }))
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/openapi.json", get(openapi))
+        .route("/:league/currency", get(currencies))
+        .route("/:league/items", get(items))
+        .route("/:league/items/:details_id", get(item))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{item_where, openapi, orderby, sort_value, ItemParams};
+    use crate::schema::{Item, ItemEndpoint, ItemOrderby, Orderby};
+
+    #[test]
+    fn sort_param() {
+        assert_eq!(
+            sort_value("-chaos_value, name,+sparkline.total_change"),
+            json!([
+                {"chaosValue": "DESC"},
+                {"name": "ASC"},
+                {"sparkline": {"totalChange": "ASC"}},
+            ])
+        );
+
+        let sorted: Vec<ItemOrderby> =
+            orderby(Some("-chaos_value"), ItemOrderby::name(Orderby::Asc)).expect("valid sort");
+        assert!(matches!(
+            sorted[..],
+            [ItemOrderby::chaos_value(Orderby::Desc)]
+        ));
+        assert!(orderby::<ItemOrderby>(Some("icon"), ItemOrderby::name(Orderby::Asc)).is_err());
+    }
+
+    #[test]
+    fn item_params() {
+        use crate::schema::filters::WhereInput;
+
+        let params = ItemParams {
+            endpoint: Some(ItemEndpoint::Scarab),
+            name: Some("gilded".into()),
+            min_chaos: Some(10.0),
+            ..Default::default()
+        };
+        let filter = item_where(&params).expect("valid params").expect("filter");
+
+        let item = |name: &str, chaos_value, endpoint| Item {
+            name: name.into(),
+            chaos_value,
+            endpoint,
+            ..Default::default()
+        };
+        assert!(filter.matches(&item(
+            "Gilded Divination Scarab",
+            20.0,
+            ItemEndpoint::Scarab
+        )));
+        assert!(!filter.matches(&item("Gilded Divination Scarab", 5.0, ItemEndpoint::Scarab)));
+        assert!(!filter.matches(&item("Gilded Divination Scarab", 20.0, ItemEndpoint::Map)));
+        assert!(!filter.matches(&item(
+            "Rusted Divination Scarab",
+            20.0,
+            ItemEndpoint::Scarab
+        )));
+
+        assert!(item_where(&ItemParams::default())
+            .expect("valid params")
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    async fn openapi_document() {
+        let document = openapi().await.0;
+
+        let paths = document["paths"].as_object().expect("paths");
+        assert_eq!(paths.len(), 3);
+
+        let league = &document["paths"]["/api/{league}/currency"]["get"]["parameters"][0];
+        let leagues = league["schema"]["enum"].as_array().expect("league values");
+        assert!(leagues.contains(&json!("TmpStandard")));
+    }
+}
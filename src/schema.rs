@@ -1,49 +1,133 @@
-use async_graphql::{Context, Object};
+use async_graphql::{extensions::Tracing, Context, EmptySubscription, Object, Schema};
 
+mod admin;
 mod cache;
+mod client;
 mod currency;
-mod filters;
+pub mod filters;
+#[cfg(test)]
+mod fixtures;
 mod item;
+mod limits;
+mod metrics;
 mod ninja_common;
 mod ninja_currency;
 mod ninja_item;
 mod orderby;
+mod search;
+mod upstream;
 
-use currency::get_currencies;
-use item::get_items;
-use ninja_common::League;
-use ninja_currency::{Currency, CurrencyOrderby, CurrencyWhere};
-use ninja_item::{Item, ItemOrderby, ItemWhere};
-use orderby::Orderby;
+pub use admin::{is_loaded, load, AdminToken, BearerToken, CacheEntry, CacheKind, MutationRoot};
+pub use cache::{set_cache_dir, set_cache_format, timestamp, Cache, CacheFormat};
+pub use client::{set_client_config, ClientConfig};
+pub use currency::get_currencies;
+pub use item::get_items;
+pub use limits::{check_where, limits, page, set_limits, LimitError, Limits, Quota, WhereLimit};
+pub use metrics::{record_rejected, render_metrics, Metrics, METRICS_CONTENT_TYPE};
+pub use ninja_common::League;
+pub use ninja_currency::{Currency, CurrencyEndpoint, CurrencyOrderby, CurrencyWhere};
+pub use ninja_item::{Item, ItemEndpoint, ItemOrderby, ItemWhere};
+pub use orderby::Orderby;
+pub use search::{search, SearchHit, SearchKind, SearchResult};
+pub use upstream::{
+    record, set_upstream, upstream_health, FetchError, Upstream, UpstreamHealth, POE_NINJA_URL,
+};
 
 pub struct QueryRoot;
 
+pub type ApiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// the admin mutations are only enabled with an admin token.
+/// queries are limited to the depth and complexity of the current [`Limits`]
+pub fn build_schema(admin_token: Option<String>) -> ApiSchema {
+    let limits = limits();
+    let builder = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .limit_depth(limits.depth)
+        .limit_complexity(limits.complexity)
+        .extension(WhereLimit)
+        .extension(Tracing)
+        .extension(Metrics);
+    match admin_token {
+        Some(token) => builder.data(AdminToken(token)).finish(),
+        None => builder.finish(),
+    }
+}
+
 static LEAGUE: &str = "Ancestor";
 static PREV_LEAGUE: &str = "Crucible";
+const DEFAULT_SEARCH_LIMIT: usize = 20;
 
 #[Object]
 impl QueryRoot {
-    async fn currency<'a>(
+    /// at most the result limit of rows, larger results are paged with limit and offset
+    async fn currency(
         &self,
-        _ctx: &Context<'a>,
-        _where: Option<CurrencyWhere>,
-        _orderby: Option<Vec<CurrencyOrderby>>,
+        _ctx: &Context<'_>,
+        #[graphql(name = "where")] filter: Option<CurrencyWhere>,
+        orderby: Option<Vec<CurrencyOrderby>>,
         league: Option<League>,
-    ) -> Vec<Currency> {
-        let orderby_arr = _orderby.unwrap_or(vec![CurrencyOrderby::name(Orderby::Asc)]);
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<Currency>> {
+        let orderby = orderby.unwrap_or_else(|| vec![CurrencyOrderby::name(Orderby::Asc)]);
 
-        get_currencies(_where, orderby_arr, league).await
+        let currencies = Box::pin(get_currencies(filter, orderby, league)).await?;
+        Ok(page(currencies, offset, limit)?)
     }
 
-    async fn item<'a>(
+    /// at most the result limit of rows, larger results are paged with limit and offset
+    async fn item(
         &self,
-        _ctx: &Context<'a>,
-        _where: Option<ItemWhere>,
-        _orderby: Option<Vec<ItemOrderby>>,
+        _ctx: &Context<'_>,
+        #[graphql(name = "where")] filter: Option<ItemWhere>,
+        orderby: Option<Vec<ItemOrderby>>,
         league: Option<League>,
-    ) -> Vec<Item> {
-        let orderby_arr = _orderby.unwrap_or(vec![ItemOrderby::name(Orderby::Asc)]);
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<Item>> {
+        let orderby = orderby.unwrap_or_else(|| vec![ItemOrderby::name(Orderby::Asc)]);
+
+        let items = Box::pin(get_items(filter, orderby, league)).await?;
+        Ok(page(items, offset, limit)?)
+    }
+
+    /// fuzzy search over item names, base types and variants and currency names,
+    /// best matches first
+    async fn search(
+        &self,
+        _ctx: &Context<'_>,
+        query: String,
+        league: Option<League>,
+        limit: Option<usize>,
+        kinds: Option<Vec<SearchKind>>,
+    ) -> async_graphql::Result<Vec<SearchResult>> {
+        let league = league.unwrap_or(League::TmpStandard);
+        let kinds = kinds.unwrap_or_else(|| vec![SearchKind::Item, SearchKind::Currency]);
+
+        let results = search(
+            &query,
+            league,
+            limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+            &kinds,
+        )
+        .await?;
+        Ok(page(results, None, None)?)
+    }
+
+    /// what is cached per league and kind, of every league and kind unless given
+    #[allow(clippy::unused_async)]
+    async fn cache_status(
+        &self,
+        league: Option<League>,
+        kind: Option<CacheKind>,
+    ) -> async_graphql::Result<Vec<CacheEntry>> {
+        admin::cache_status(league, kind)
+    }
 
-        get_items(_where, orderby_arr, league).await
+    /// requests left to the api key or address of this request, absent outside of
+    /// the server
+    #[allow(clippy::unused_async)]
+    async fn quota(&self, ctx: &Context<'_>) -> Option<Quota> {
+        ctx.data_opt::<Quota>().cloned()
     }
 }
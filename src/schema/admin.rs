@@ -0,0 +1,199 @@
+use async_graphql::{Context, Enum, EnumType, Guard, Object, Result, SimpleObject};
+
+use super::cache::{cache_info, invalidate, is_remembered, timestamp, CACHE_THRESHOLD};
+use super::currency::{load_currencies, refetch_currencies, CURRENCY_ENDPOINTS};
+use super::item::{load_items, refetch_items, ITEM_ENDPOINTS};
+use super::ninja_common::League;
+use super::search::invalidate_index;
+use super::upstream::{forget_responses, is_offline, FetchError, Overview};
+
+/// token guarding the admin mutations, they are disabled without one
+#[derive(Debug, Clone)]
+pub struct AdminToken(pub String);
+
+/// bearer token sent with the request
+#[derive(Debug, Clone)]
+pub struct BearerToken(pub String);
+
+/// compares every byte so the time taken does not leak the matching prefix
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+struct AdminGuard;
+
+impl Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let Some(AdminToken(admin)) = ctx.data_opt::<AdminToken>() else {
+            return Err("admin mutations are disabled, no admin token is configured".into());
+        };
+        match ctx.data_opt::<BearerToken>() {
+            Some(BearerToken(token)) if tokens_match(token, admin) => Ok(()),
+            _ => Err("invalid admin token".into()),
+        }
+    }
+}
+
+#[derive(Debug, Enum, Clone, Copy, Eq, PartialEq)]
+pub enum CacheKind {
+    Item,
+    Currency,
+}
+
+impl CacheKind {
+    const fn fetch_type(self) -> &'static str {
+        match self {
+            Self::Item => "item",
+            Self::Currency => "currency",
+        }
+    }
+
+    const fn overview(self) -> Overview {
+        match self {
+            Self::Item => Overview::Item,
+            Self::Currency => Overview::Currency,
+        }
+    }
+
+    fn endpoints(self) -> Vec<String> {
+        match self {
+            Self::Item => ITEM_ENDPOINTS.map(|endpoint| endpoint.to_string()).to_vec(),
+            Self::Currency => CURRENCY_ENDPOINTS
+                .map(|endpoint| endpoint.to_string())
+                .to_vec(),
+        }
+    }
+
+    /// the given kind, or all of them
+    fn or_all(kind: Option<Self>) -> Vec<Self> {
+        kind.map_or_else(|| vec![Self::Item, Self::Currency], |kind| vec![kind])
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct CacheEntry {
+    pub league: League,
+    pub kind: CacheKind,
+    /// unix timestamp in seconds
+    pub fetched_at: u64,
+    /// seconds since the data was fetched
+    pub age: u64,
+    /// younger than the refresh threshold
+    pub fresh: bool,
+    /// bytes on disk
+    pub size: u64,
+    pub rows: usize,
+    /// upstream the data was fetched from
+    pub source: String,
+    /// corrupt entries and entries of other versions are fetched again
+    pub valid: bool,
+}
+
+/// cached data of the league and kind, of every league and kind unless given
+pub fn cache_status(league: Option<League>, kind: Option<CacheKind>) -> Result<Vec<CacheEntry>> {
+    let leagues = league.map_or_else(
+        || League::items().iter().map(|item| item.value).collect(),
+        |league| vec![league],
+    );
+    let now = timestamp();
+
+    let mut entries = Vec::new();
+    for league in leagues {
+        for kind in CacheKind::or_all(kind) {
+            if let Some(info) = cache_info(kind.fetch_type(), league, &kind.endpoints())? {
+                let age = now.saturating_sub(info.fetch_time);
+                entries.push(CacheEntry {
+                    league,
+                    kind,
+                    fetched_at: info.fetch_time,
+                    age,
+                    fresh: info.valid && age < CACHE_THRESHOLD,
+                    size: info.size,
+                    rows: info.rows,
+                    source: info.source,
+                    valid: info.valid,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// reads or fetches the data of the league and kind
+pub async fn load(league: League, kind: CacheKind) -> Result<(), FetchError> {
+    match kind {
+        CacheKind::Item => drop(load_items(league).await?),
+        CacheKind::Currency => drop(load_currencies(league).await?),
+    }
+    Ok(())
+}
+
+/// fetches the data of the league and kind again, the cached data is kept if that fails
+async fn refresh(league: League, kind: CacheKind) -> Result<(), FetchError> {
+    match kind {
+        CacheKind::Item => drop(refetch_items(league).await?),
+        CacheKind::Currency => drop(refetch_currencies(league).await?),
+    }
+    Ok(())
+}
+
+/// whether the data of the league and kind was read or fetched since it was last
+/// invalidated. recordings are read on every request, so they are always loaded
+pub fn is_loaded(league: League, kind: CacheKind) -> bool {
+    is_offline() || is_remembered(kind.fetch_type(), league)
+}
+
+fn invalidate_cache(league: League, kind: Option<CacheKind>) -> Result<usize> {
+    let mut removed = 0;
+    for kind in CacheKind::or_all(kind) {
+        // the stored responses go too, otherwise they would only be revalidated
+        let forgotten = forget_responses(kind.overview(), league, &kind.endpoints())?;
+        removed += usize::from(invalidate(kind.fetch_type(), league)? || forgotten);
+    }
+    invalidate_index(league);
+    Ok(removed)
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// drops the cached data of the league, of every kind unless given, so that the next
+    /// request downloads it again in full. returns how many entries were dropped
+    #[graphql(guard = "AdminGuard")]
+    #[allow(clippy::unused_async)]
+    async fn invalidate_cache(&self, league: League, kind: Option<CacheKind>) -> Result<usize> {
+        invalidate_cache(league, kind)
+    }
+
+    /// fetches the data of the league again right away, of every kind unless given.
+    /// the cached data is only replaced by data that was fetched
+    #[graphql(guard = "AdminGuard")]
+    async fn refresh_now(
+        &self,
+        league: League,
+        kind: Option<CacheKind>,
+    ) -> Result<Vec<CacheEntry>> {
+        for kind in CacheKind::or_all(kind) {
+            refresh(league, kind).await?;
+        }
+        cache_status(Some(league), kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens_match;
+
+    #[test]
+    fn matching_tokens() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secret", "secreT"));
+        assert!(!tokens_match("secret", "secret2"));
+        assert!(!tokens_match("", "secret"));
+    }
+}
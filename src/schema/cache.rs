@@ -1,63 +1,640 @@
 use std::{
+    any::Any,
+    collections::HashMap,
     future::Future,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock, Mutex, RwLock,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Serialize,
+};
+use serde_bytes::ByteBuf;
+use serde_json::value::RawValue;
+use tracing::{debug, field, info, Span};
 
+use super::metrics::{record_cache_hit, record_cache_miss};
 use super::ninja_common::League;
+use super::search::invalidate_index;
+use super::upstream::{is_offline, upstream, FetchError};
+
+pub const CACHE_THRESHOLD: u64 = 60 * 60;
+
+const ZSTD_LEVEL: i32 = 3;
 
-const CACHE_THRESHOLD: u64 = 60 * 60;
+static CACHE_DIR: LazyLock<RwLock<PathBuf>> = LazyLock::new(|| RwLock::new(std::env::temp_dir()));
+static CACHE_FORMAT: RwLock<CacheFormat> = RwLock::new(CacheFormat::Json);
+
+/// directory of the cache files, the system temp dir by default
+pub fn set_cache_dir(dir: PathBuf) {
+    *CACHE_DIR.write().expect("cache dir lock poisoned") = dir;
+}
+
+/// encoding of the cache files written from now on
+pub fn set_cache_format(format: CacheFormat) {
+    *CACHE_FORMAT.write().expect("cache format lock poisoned") = format;
+}
+
+fn cache_format() -> CacheFormat {
+    *CACHE_FORMAT.read().expect("cache format lock poisoned")
+}
 
+/// bumped whenever the layout of the cache files or the values derived while fetching
+/// change, 1 being the unversioned files. 3 has sign-free modifier templates
+pub const CACHE_FORMAT_VERSION: u32 = 3;
+
+/// envelope of the cached data, checked before the data is used
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct Cache<T> {
+    pub format_version: u32,
+    /// models may change between versions, so a cache is only read by the version writing it
+    pub crate_version: String,
+    pub league: String,
+    /// endpoints the data was fetched from
+    pub endpoints: Vec<String>,
     pub fetch_time: i64,
+    /// upstream the data was fetched from
+    pub source: String,
+    /// crc32 of the serialized data
+    pub checksum: u32,
     pub data: T,
 }
 
+impl<T> Cache<T> {
+    /// envelope of `data`, which is `raw` once serialized
+    fn new(league: League, endpoints: &[String], fetch_time: u64, raw: &[u8], data: T) -> Self {
+        Self {
+            format_version: CACHE_FORMAT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            league: league.to_string(),
+            endpoints: endpoints.to_vec(),
+            fetch_time: fetch_time.try_into().unwrap_or(i64::MAX),
+            source: upstream().to_string(),
+            checksum: crc32fast::hash(raw),
+            data,
+        }
+    }
+
+    /// of this version, league and endpoints, and `raw` matches the checksum
+    fn is_valid(&self, raw: &[u8], league: League, endpoints: &[String]) -> bool {
+        self.format_version == CACHE_FORMAT_VERSION
+            && self.crate_version == env!("CARGO_PKG_VERSION")
+            && self.league == league.to_string()
+            && self.endpoints == endpoints
+            && crc32fast::hash(raw) == self.checksum
+    }
+
+    fn with_data<U>(self, data: U) -> Cache<U> {
+        Cache {
+            format_version: self.format_version,
+            crate_version: self.crate_version,
+            league: self.league,
+            endpoints: self.endpoints,
+            fetch_time: self.fetch_time,
+            source: self.source,
+            checksum: self.checksum,
+            data,
+        }
+    }
+}
+
+/// encoding of the cache files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CacheFormat {
+    #[default]
+    Json,
+    /// zstd compressed `MessagePack`, smaller and faster to load
+    Zstd,
+}
+
+impl CacheFormat {
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Zstd => "msgpack.zst",
+        }
+    }
+
+    pub fn encode<T: Serialize>(
+        self,
+        league: League,
+        endpoints: &[String],
+        fetch_time: u64,
+        data: &T,
+    ) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Json => {
+                let data = serde_json::value::to_raw_value(data)?;
+                let cache = Cache::new(league, endpoints, fetch_time, data.get().as_bytes(), &data);
+                Ok(serde_json::to_vec(&cache)?)
+            }
+            Self::Zstd => {
+                let data =
+                    ByteBuf::from(rmp_serde::to_vec_named(data).map_err(std::io::Error::other)?);
+                let cache = Cache::new(league, endpoints, fetch_time, &data, &data);
+                let bytes = rmp_serde::to_vec_named(&cache).map_err(std::io::Error::other)?;
+                zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL)
+            }
+        }
+    }
+
+    /// `None` if the bytes are corrupt or written by an incompatible version
+    pub fn decode<T: DeserializeOwned>(
+        self,
+        bytes: &[u8],
+        league: League,
+        endpoints: &[String],
+    ) -> Option<Cache<T>> {
+        match self {
+            Self::Json => {
+                let cache = serde_json::from_slice::<Cache<&RawValue>>(bytes).ok()?;
+                let raw = cache.data.get();
+                if !cache.is_valid(raw.as_bytes(), league, endpoints) {
+                    return None;
+                }
+                let data = serde_json::from_str(raw).ok()?;
+                Some(cache.with_data(data))
+            }
+            Self::Zstd => {
+                let bytes = zstd::decode_all(bytes).ok()?;
+                let cache = rmp_serde::from_slice::<Cache<ByteBuf>>(&bytes).ok()?;
+                if !cache.is_valid(&cache.data, league, endpoints) {
+                    return None;
+                }
+                let data = rmp_serde::from_slice(&cache.data).ok()?;
+                Some(cache.with_data(data))
+            }
+        }
+    }
+}
+
+/// what is stored for a data type and league
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheInfo {
+    pub fetch_time: u64,
+    /// bytes on disk
+    pub size: u64,
+    pub rows: usize,
+    pub source: String,
+    /// corrupt files and files of other versions are fetched again
+    pub valid: bool,
+}
+
+/// file named `name` in the cache dir
+pub fn cache_path(name: &str) -> PathBuf {
+    CACHE_DIR
+        .read()
+        .expect("cache dir lock poisoned")
+        .join(name)
+}
+
+fn data_path(fetch_type: &str, league: League, format: CacheFormat) -> PathBuf {
+    cache_path(&format!(
+        "__poe__{}__{}.{}",
+        fetch_type,
+        league.to_string(),
+        format.extension()
+    ))
+}
+
+/// `None` if nothing is cached, without deserializing the rows themselves
+pub fn cache_info(
+    fetch_type: &str,
+    league: League,
+    endpoints: &[String],
+) -> std::io::Result<Option<CacheInfo>> {
+    let format = cache_format();
+    let bytes = match std::fs::read(data_path(fetch_type, league, format)) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let cache = format.decode::<Vec<IgnoredAny>>(&bytes, league, endpoints);
+    Ok(Some(CacheInfo {
+        fetch_time: cache
+            .as_ref()
+            .map_or(0, |cache| cache.fetch_time.try_into().unwrap_or_default()),
+        size: bytes.len() as u64,
+        rows: cache.as_ref().map_or(0, |cache| cache.data.len()),
+        valid: cache.is_some(),
+        source: cache.map(|cache| cache.source).unwrap_or_default(),
+    }))
+}
+
+/// the cached data, `None` if it is missing, corrupt or written by an incompatible version
+fn read_cache<T: DeserializeOwned>(
+    path: &Path,
+    format: CacheFormat,
+    league: League,
+    endpoints: &[String],
+) -> Option<Cache<T>> {
+    let bytes = std::fs::read(path).ok()?;
+    format.decode(&bytes, league, endpoints)
+}
+
+static TMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// written to a temporary file first, so that an interrupted write leaves no partial file.
+/// every write has its own temporary file, so concurrent writes do not mix
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let tmp_path = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        TMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written =
+        std::fs::write(&tmp_path, contents).and_then(|()| std::fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+fn write_cache<T: Serialize>(
+    path: &Path,
+    format: CacheFormat,
+    league: League,
+    endpoints: &[String],
+    fetch_time: u64,
+    data: &T,
+) -> std::io::Result<()> {
+    let bytes = format.encode(league, endpoints, fetch_time, data)?;
+    write_atomically(path, bytes)
+}
+
+/// data that was read or fetched, so fresh data is only decoded once
+#[derive(Clone)]
+struct Remembered {
+    fetch_time: u64,
+    data: Arc<dyn Any + Send + Sync>,
+}
+
+static MEMORY: LazyLock<Mutex<HashMap<(String, League), Remembered>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// held while the data of a kind and league is fetched, so concurrent requests share one fetch
+static FETCHES: LazyLock<Mutex<HashMap<(String, League), FetchLock>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+type FetchLock = Arc<tokio::sync::Mutex<()>>;
+
+fn fetch_lock(fetch_type: &str, league: League) -> FetchLock {
+    let mut fetches = FETCHES.lock().expect("fetch lock poisoned");
+    Arc::clone(fetches.entry((fetch_type.to_string(), league)).or_default())
+}
+
+fn recall<T: Send + Sync + 'static>(fetch_type: &str, league: League, now: u64) -> Option<Arc<T>> {
+    let remembered = MEMORY
+        .lock()
+        .expect("cache memory lock poisoned")
+        .get(&(fetch_type.to_string(), league))
+        .cloned()?;
+    if now.saturating_sub(remembered.fetch_time) >= CACHE_THRESHOLD {
+        return None;
+    }
+    remembered.data.downcast::<T>().ok()
+}
+
+/// kind, league and fetch time of the data in memory
+pub fn remembered() -> Vec<(String, League, u64)> {
+    MEMORY
+        .lock()
+        .expect("cache memory lock poisoned")
+        .iter()
+        .map(|((fetch_type, league), remembered)| {
+            (fetch_type.clone(), *league, remembered.fetch_time)
+        })
+        .collect()
+}
+
+/// whether the data was read or fetched, fresh or not
+pub fn is_remembered(fetch_type: &str, league: League) -> bool {
+    MEMORY
+        .lock()
+        .expect("cache memory lock poisoned")
+        .contains_key(&(fetch_type.to_string(), league))
+}
+
+/// whether the data in memory is younger than the cache threshold
+pub fn is_fresh(fetch_type: &str, league: League) -> bool {
+    let now = timestamp();
+    MEMORY
+        .lock()
+        .expect("cache memory lock poisoned")
+        .get(&(fetch_type.to_string(), league))
+        .is_some_and(|remembered| now.saturating_sub(remembered.fetch_time) < CACHE_THRESHOLD)
+}
+
+fn remember<T: Send + Sync + 'static>(
+    fetch_type: &str,
+    league: League,
+    fetch_time: u64,
+    data: Arc<T>,
+) {
+    MEMORY.lock().expect("cache memory lock poisoned").insert(
+        (fetch_type.to_string(), league),
+        Remembered { fetch_time, data },
+    );
+    // the search index is built from the data in memory
+    invalidate_index(league);
+}
+
+/// removes the cached data, the next request fetches it again.
+/// returns whether anything was cached
+pub fn invalidate(fetch_type: &str, league: League) -> std::io::Result<bool> {
+    let mut removed = MEMORY
+        .lock()
+        .expect("cache memory lock poisoned")
+        .remove(&(fetch_type.to_string(), league))
+        .is_some();
+
+    for format in [CacheFormat::Json, CacheFormat::Zstd] {
+        match std::fs::remove_file(data_path(fetch_type, league, format)) {
+            Ok(()) => removed = true,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(removed)
+}
+
 /// returns the current timestamp in seconds
-fn timestamp() -> u64 {
+pub fn timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards")
         .as_secs()
 }
 
+/// the cached data if it is fresh and valid, fetched and cached again otherwise.
+/// fresh data is kept in memory, so the cache file is only decoded once and every
+/// request shares it
+#[tracing::instrument(
+    name = "cache",
+    skip_all,
+    fields(kind = fetch_type, league = ?league, decision = field::Empty, layer = field::Empty)
+)]
 pub async fn fetch_with_cache<T, FetchFn, Fut>(
     fetch_type: &str,
     league: League,
+    endpoints: &[String],
     fetch_fn: FetchFn,
-) -> std::io::Result<T>
+) -> Result<Arc<T>, FetchError>
 where
-    T: DeserializeOwned + Serialize + Clone,
+    T: DeserializeOwned + Serialize + Send + Sync + 'static,
     FetchFn: FnOnce() -> Fut,
-    Fut: Future<Output = T>,
+    Fut: Future<Output = Result<T, FetchError>>,
 {
-    let fetch_time = timestamp();
+    let span = Span::current();
+    if is_offline() {
+        span.record("decision", "offline");
+        return fetch_fn().await.map(Arc::new);
+    }
 
-    let fname = format!("/tmp/__poe__{}__{}.json", fetch_type, league.to_string());
-    let cache_path = PathBuf::from(fname);
+    if let Some(data) = recall(fetch_type, league, timestamp()) {
+        span.record("decision", "hit").record("layer", "memory");
+        debug!("fresh data in memory");
+        record_cache_hit(fetch_type, "memory");
+        return Ok(data);
+    }
+
+    // whoever held the lock may have fetched the data meanwhile
+    let fetch_lock = fetch_lock(fetch_type, league);
+    let _fetching = fetch_lock.lock().await;
+    let fetch_time = timestamp();
+    if let Some(data) = recall(fetch_type, league, fetch_time) {
+        span.record("decision", "hit").record("layer", "memory");
+        debug!("data fetched by a concurrent request");
+        record_cache_hit(fetch_type, "memory");
+        return Ok(data);
+    }
 
-    // use cache if it is available
-    if cache_path.exists() {
-        let cache = std::fs::read_to_string(&cache_path)?;
-        let cache = serde_json::from_str::<Cache<T>>(&cache)?;
+    let format = cache_format();
+    let cache_path = data_path(fetch_type, league, format);
 
-        // use cache if it is not older than 1 hour
-        if fetch_time - (cache.fetch_time as u64) < CACHE_THRESHOLD {
-            return Ok(cache.data);
+    // use cache if it is not older than 1 hour
+    let decision = match read_cache::<T>(&cache_path, format, league, endpoints) {
+        Some(cache) => {
+            let cached_at = cache.fetch_time.try_into().unwrap_or_default();
+            let age = fetch_time.saturating_sub(cached_at);
+            if age < CACHE_THRESHOLD {
+                span.record("decision", "hit").record("layer", "disk");
+                debug!(age, "fresh data on disk");
+                record_cache_hit(fetch_type, "disk");
+                let data = Arc::new(cache.data);
+                remember(fetch_type, league, cached_at, Arc::clone(&data));
+                return Ok(data);
+            }
+            "stale"
         }
+        None => "miss",
+    };
+
+    // cache not available, outdated or unreadable, fetch data
+    span.record("decision", decision);
+    info!(path = %cache_path.display(), "cache {decision}, fetching data");
+    record_cache_miss(fetch_type);
+    let data = fetch_fn().await?;
+    store(fetch_type, league, endpoints, fetch_time, data)
+}
+
+/// fetches the data again however fresh the cache is, after any fetch in progress.
+/// the cached data is only replaced once the fetch succeeded, so a failed fetch keeps
+/// serving it
+pub async fn refetch<T, FetchFn, Fut>(
+    fetch_type: &str,
+    league: League,
+    endpoints: &[String],
+    fetch_fn: FetchFn,
+) -> Result<Arc<T>, FetchError>
+where
+    T: Serialize + Send + Sync + 'static,
+    FetchFn: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T, FetchError>>,
+{
+    if is_offline() {
+        return fetch_fn().await.map(Arc::new);
     }
 
-    let data = fetch_fn().await;
-    // cache not available or outdated, fetch data
-    let cache = Cache {
-        fetch_time: fetch_time as i64,
-        data: &data,
-    };
-    let cache = serde_json::to_string(&cache)?;
-    std::fs::write(&cache_path, cache)?;
+    let fetch_lock = fetch_lock(fetch_type, league);
+    let _fetching = fetch_lock.lock().await;
+    let fetch_time = timestamp();
+    let data = fetch_fn().await?;
+    info!(kind = fetch_type, ?league, "data fetched again");
+    store(fetch_type, league, endpoints, fetch_time, data)
+}
+
+/// writes fetched data to the cache file and keeps it in memory
+fn store<T: Serialize + Send + Sync + 'static>(
+    fetch_type: &str,
+    league: League,
+    endpoints: &[String],
+    fetch_time: u64,
+    data: T,
+) -> Result<Arc<T>, FetchError> {
+    let format = cache_format();
+    let cache_path = data_path(fetch_type, league, format);
+    write_cache(&cache_path, format, league, endpoints, fetch_time, &data)?;
+    let data = Arc::new(data);
+    remember(fetch_type, league, fetch_time, Arc::clone(&data));
 
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use super::{
+        read_cache, recall, remember, timestamp, write_atomically, write_cache, CacheFormat,
+        CACHE_FORMAT_VERSION, CACHE_THRESHOLD,
+    };
+    use crate::schema::{fixtures::items, ninja_common::League, ninja_item::Item};
+
+    const FORMATS: [CacheFormat; 2] = [CacheFormat::Json, CacheFormat::Zstd];
+
+    fn cache_file(name: &str, format: CacheFormat) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("poe-api-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create cache dir");
+        dir.join(format!("{name}.{}", format.extension()))
+    }
+
+    fn endpoints() -> Vec<String> {
+        vec!["Currency".to_string(), "Fragment".to_string()]
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        for format in FORMATS {
+            let path = cache_file("roundtrip", format);
+            write_cache(&path, format, League::Standard, &endpoints(), 42, &items())
+                .expect("write");
+
+            let cache = read_cache::<Vec<Item>>(&path, format, League::Standard, &endpoints())
+                .expect("cache");
+            assert_eq!(
+                serde_json::to_value(cache.data).expect("json"),
+                serde_json::to_value(items()).expect("json")
+            );
+            assert_eq!(cache.fetch_time, 42);
+            assert_eq!(cache.format_version, CACHE_FORMAT_VERSION);
+        }
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        let path = cache_file("concurrent", CacheFormat::Json);
+        std::thread::scope(|scope| {
+            for writer in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    write_atomically(path, writer.to_string().repeat(10_000)).expect("write");
+                });
+            }
+        });
+
+        let written = std::fs::read_to_string(&path).expect("read");
+        assert_eq!(written.len(), 10_000);
+        assert!(written
+            .chars()
+            .all(|c| c == written.chars().next().unwrap_or_default()));
+        let tmp_files = std::fs::read_dir(path.parent().expect("cache dir"))
+            .expect("read dir")
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with("concurrent.") && name.ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(tmp_files, 0);
+    }
+
+    #[test]
+    fn zstd_is_smaller() {
+        let size = |format: CacheFormat| {
+            format
+                .encode(League::Standard, &endpoints(), 42, &items())
+                .expect("encode")
+                .len()
+        };
+        assert!(size(CacheFormat::Zstd) * 4 < size(CacheFormat::Json));
+    }
+
+    #[test]
+    fn rejects_other_leagues_and_endpoints() {
+        for format in FORMATS {
+            let path = cache_file("mismatch", format);
+            write_cache(&path, format, League::Standard, &endpoints(), 42, &vec![1])
+                .expect("write");
+
+            let read = |league, endpoints: &[String]| {
+                read_cache::<Vec<i32>>(&path, format, league, endpoints)
+            };
+            assert!(read(League::Hardcore, &endpoints()).is_none());
+            assert!(read(League::Standard, &endpoints()[..1]).is_none());
+            // the model changed
+            assert!(
+                read_cache::<Vec<String>>(&path, format, League::Standard, &endpoints()).is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        for format in FORMATS {
+            let path = cache_file("corrupt", format);
+            write_cache(
+                &path,
+                format,
+                League::Standard,
+                &endpoints(),
+                42,
+                &vec![1, 2, 3],
+            )
+            .expect("write");
+            let bytes = std::fs::read(&path).expect("read");
+
+            let mut flipped = bytes.clone();
+            let last = flipped.len() - 8;
+            flipped[last] ^= 1;
+            std::fs::write(&path, flipped).expect("write");
+            assert!(
+                read_cache::<Vec<i32>>(&path, format, League::Standard, &endpoints()).is_none()
+            );
+
+            std::fs::write(&path, &bytes[..bytes.len() / 2]).expect("write");
+            assert!(
+                read_cache::<Vec<i32>>(&path, format, League::Standard, &endpoints()).is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unversioned_files() {
+        let path = cache_file("unversioned", CacheFormat::Json);
+        std::fs::write(&path, r#"{"fetch_time": 42, "data": [1, 2, 3]}"#).expect("write");
+
+        assert!(
+            read_cache::<Vec<i32>>(&path, CacheFormat::Json, League::Standard, &endpoints())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn recall_shares_the_data() {
+        let now = timestamp();
+        remember("shared", League::Standard, now, Arc::new(items()));
+
+        let first = recall::<Vec<Item>>("shared", League::Standard, now).expect("fresh");
+        let second = recall::<Vec<Item>>("shared", League::Standard, now).expect("fresh");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(recall::<Vec<i32>>("shared", League::Standard, now).is_none());
+        assert!(recall::<Vec<Item>>("shared", League::Standard, now + CACHE_THRESHOLD).is_none());
+    }
+}
@@ -0,0 +1,251 @@
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER},
+    Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, LazyLock, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+pub const USER_AGENT: &str = concat!("poe-api/", env!("CARGO_PKG_VERSION"));
+
+/// how poe.ninja is queried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// of a single attempt
+    pub timeout: Duration,
+    /// attempts after the first one, on timeouts, 429 and 5xx
+    pub retries: u32,
+    /// doubled after every attempt, unless the response has a `Retry-After`
+    pub backoff: Duration,
+    /// longest wait between attempts, `Retry-After` included
+    pub max_backoff: Duration,
+    /// requests in flight at once
+    pub concurrency: usize,
+    pub user_agent: String,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_mins(1),
+            concurrency: 4,
+            user_agent: USER_AGENT.to_string(),
+        }
+    }
+}
+
+/// `ETag` and `Last-Modified` of a response, sent back to only download changed data
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let values = [
+            (IF_NONE_MATCH, &self.etag),
+            (IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in values {
+            if let Some(value) = value.as_deref().and_then(|value| value.parse().ok()) {
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+}
+
+/// pooled http client shared by every upstream request
+#[derive(Debug)]
+pub struct UpstreamClient {
+    http: reqwest::Client,
+    config: ClientConfig,
+    permits: Semaphore,
+}
+
+impl UpstreamClient {
+    pub fn new(config: ClientConfig) -> reqwest::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .build()?;
+
+        Ok(Self {
+            http,
+            permits: Semaphore::new(config.concurrency.max(1)),
+            config,
+        })
+    }
+
+    /// body of a successful response, retrying transient failures
+    pub async fn get_text(&self, url: &str) -> reqwest::Result<String> {
+        self.send(url, HeaderMap::new())
+            .await?
+            .error_for_status()?
+            .text()
+            .await
+    }
+
+    /// body and validators of a successful response, `None` if the server answered
+    /// 304 Not Modified to the validators of the previous response
+    pub async fn get_text_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> reqwest::Result<Option<(String, Validators)>> {
+        let response = self.send(url, validators.headers()).await?;
+        if response.status() == StatusCode::NOT_MODIFIED && !validators.is_empty() {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+        let validators = Validators::from_headers(response.headers());
+        Ok(Some((response.text().await?, validators)))
+    }
+
+    /// the first response that is not a transient failure, or the last one
+    async fn send(&self, url: &str, headers: HeaderMap) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let response = {
+                let _permit = self
+                    .permits
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed");
+                self.http
+                    .get(url)
+                    .headers(headers.clone())
+                    .timeout(self.config.timeout)
+                    .send()
+                    .await
+            };
+
+            let delay = match response {
+                Ok(response)
+                    if attempt < self.config.retries && is_transient(response.status()) =>
+                {
+                    warn!(url, status = %response.status(), attempt, "retrying poe.ninja request");
+                    retry_after(&response).unwrap_or_else(|| self.backoff(attempt))
+                }
+                Ok(response) => return Ok(response),
+                Err(err)
+                    if attempt < self.config.retries && (err.is_timeout() || err.is_connect()) =>
+                {
+                    warn!(url, error = %err, attempt, "retrying poe.ninja request");
+                    self.backoff(attempt)
+                }
+                Err(err) => return Err(err),
+            };
+
+            tokio::time::sleep(delay.min(self.config.max_backoff)).await;
+            attempt += 1;
+        }
+    }
+
+    const fn backoff(&self, attempt: u32) -> Duration {
+        self.config
+            .backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` as either delay seconds or an http date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, SystemTime::now())
+}
+
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+static CLIENT: LazyLock<RwLock<Arc<UpstreamClient>>> = LazyLock::new(|| {
+    let client = UpstreamClient::new(ClientConfig::default()).expect("default http client");
+    RwLock::new(Arc::new(client))
+});
+
+/// replaces the shared client, requests in flight finish on the previous one
+pub fn set_client_config(config: ClientConfig) -> reqwest::Result<()> {
+    let client = Arc::new(UpstreamClient::new(config)?);
+    *CLIENT.write().expect("client lock poisoned") = client;
+    Ok(())
+}
+
+pub fn client() -> Arc<UpstreamClient> {
+    Arc::clone(&CLIENT.read().expect("client lock poisoned"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{parse_retry_after, ClientConfig, UpstreamClient};
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").expect("date");
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // dates in the past mean right away
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", SystemTime::now()), None);
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let client = UpstreamClient::new(ClientConfig {
+            backoff: Duration::from_millis(100),
+            ..ClientConfig::default()
+        })
+        .expect("client");
+
+        assert_eq!(client.backoff(0), Duration::from_millis(100));
+        assert_eq!(client.backoff(3), Duration::from_millis(800));
+        // saturates instead of overflowing, max_backoff caps it when waiting
+        assert!(client.backoff(40) > Duration::from_secs(60 * 60 * 24));
+    }
+}
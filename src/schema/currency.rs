@@ -1,58 +1,43 @@
 use futures::future;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use super::cache::fetch_with_cache;
+use super::cache::{fetch_with_cache, refetch};
 use super::filters::WhereInput;
 use super::ninja_common::League;
 use super::ninja_currency::{
     Currency, CurrencyEndpoint, CurrencyOrderby, CurrencyRaw, CurrencyWhere,
 };
 use super::orderby::OrderbyInput;
+use super::upstream::{fetch_overview, FetchError, Overview};
 
-async fn fetch_currency_endpoint(league: League, endpoint: &CurrencyEndpoint) -> CurrencyRaw {
-    let endpoint_str = endpoint.to_string();
-    let url = format!(
-        "https://poe.ninja/api/data/currencyoverview?league={}&type={}",
-        league.to_string(),
-        endpoint_str
-    );
-    let mut currencies = reqwest::get(url)
-        .await
-        .unwrap_or_else(|_| {
-            panic!(
-                "could not fetch currency data from endpoint: {}",
-                endpoint_str
-            )
-        })
-        .json::<CurrencyRaw>()
-        .await
-        .unwrap_or_else(|_| {
-            panic!(
-                "could not parse currency data from endpoint: {}",
-                endpoint_str
-            )
-        });
+async fn fetch_currency_endpoint(
+    league: League,
+    endpoint: &CurrencyEndpoint,
+) -> Result<CurrencyRaw, FetchError> {
+    let mut currencies: CurrencyRaw =
+        fetch_overview(Overview::Currency, league, &endpoint.to_string()).await?;
 
     // add endpoint information
     currencies.lines.iter_mut().for_each(|line| {
         line.endpoint = *endpoint;
     });
-    currencies
+    Ok(currencies)
 }
 
-async fn fetch_currencies(league: League) -> Vec<Currency> {
-    // let currencies: CurrencyRaw = serde_json::from_str(include_str!("currencies.json"))
-    //     .expect("failed to parse currencies.json");
+/// every endpoint that makes up the currencies of a league
+pub const CURRENCY_ENDPOINTS: [CurrencyEndpoint; 2] =
+    [CurrencyEndpoint::Currency, CurrencyEndpoint::Fragment];
 
+async fn fetch_currencies(league: League) -> Result<Vec<Currency>, FetchError> {
     // fetch multiple requests and join them
     // https://stackoverflow.com/a/75590180
 
-    let responses = future::join_all(
-        [CurrencyEndpoint::Currency, CurrencyEndpoint::Fragment]
+    let responses = future::try_join_all(
+        CURRENCY_ENDPOINTS
             .iter()
             .map(|endpoint| async move { fetch_currency_endpoint(league, endpoint).await }),
     )
-    .await;
+    .await?;
 
     let currencies = responses
         .into_iter()
@@ -75,7 +60,7 @@ async fn fetch_currencies(league: League) -> Vec<Currency> {
         })
         .collect();
 
-    currencies
+    Ok(currencies
         .currency_details
         .into_iter()
         .filter_map(|detail| {
@@ -97,30 +82,43 @@ async fn fetch_currencies(league: League) -> Vec<Currency> {
                 None
             }
         })
-        .collect()
+        .collect())
 }
 
-pub async fn get_currencies(
-    _where: Option<CurrencyWhere>,
-    _orderby: Vec<CurrencyOrderby>,
-    league: Option<League>,
-) -> Vec<Currency> {
-    let league = league.unwrap_or(League::TmpStandard);
+/// all currencies of the league, from the cache if it is fresh
+pub async fn load_currencies(league: League) -> Result<Arc<Vec<Currency>>, FetchError> {
+    let endpoints = CURRENCY_ENDPOINTS.map(|endpoint| endpoint.to_string());
+    fetch_with_cache("currency", league, &endpoints, || async {
+        fetch_currencies(league).await
+    })
+    .await
+}
 
-    let currencies = fetch_with_cache("currency", league, || async {
+/// all currencies of the league fetched again, the cache is kept if that fails
+pub async fn refetch_currencies(league: League) -> Result<Arc<Vec<Currency>>, FetchError> {
+    let endpoints = CURRENCY_ENDPOINTS.map(|endpoint| endpoint.to_string());
+    refetch("currency", league, &endpoints, || async {
         fetch_currencies(league).await
     })
     .await
-    // error will be bubbled up
-    .unwrap();
+}
+
+pub async fn get_currencies(
+    filter: Option<CurrencyWhere>,
+    orderby: Vec<CurrencyOrderby>,
+    league: Option<League>,
+) -> Result<Vec<Currency>, FetchError> {
+    let league = league.unwrap_or(League::TmpStandard);
+
+    let currencies = load_currencies(league).await?;
 
-    let mut currencies = if let Some(_where) = _where {
-        _where.filter_recursive(&currencies)
+    let mut currencies = if let Some(filter) = filter {
+        filter.filter_recursive(&currencies)
     } else {
-        currencies
+        Vec::clone(&currencies)
     };
 
-    CurrencyOrderby::orderby(&mut currencies, _orderby);
+    CurrencyOrderby::orderby(&mut currencies, orderby);
 
-    currencies
+    Ok(currencies)
 }
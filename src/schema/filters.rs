@@ -1,13 +1,12 @@
-use async_graphql::InputObject;
+use async_graphql::{InputObject, InputType};
 use regex::Regex;
-use std::collections::HashSet;
-use std::fmt::Debug;
-use std::hash::Hash;
+use std::{fmt::Debug, sync::OnceLock};
 
-use super::{
-    ninja_currency::CurrencyEndpoint,
-    ninja_item::{ItemEndpoint, Modifier},
-};
+use super::limits::RegexValidator;
+use super::ninja_item::{Modifier, ModifierValue};
+
+// enum filters are generated next to their enums by derive(GQLFilter)
+pub use super::{ninja_currency::CurrencyEndpointFilter, ninja_item::ItemEndpointFilter};
 
 #[derive(Debug, InputObject)]
 pub struct StringFilter {
@@ -21,10 +20,17 @@ pub struct StringFilter {
     pub _istartswith: Option<String>,
     pub _endswith: Option<String>,
     pub _iendswith: Option<String>,
+    #[graphql(validator(custom = "RegexValidator"))]
     pub _regex: Option<String>,
+    #[graphql(validator(custom = "RegexValidator"))]
     pub _iregex: Option<String>,
     pub _in: Option<Vec<String>>,
     pub _nin: Option<Vec<String>>,
+    /// `_regex` and `_iregex` compiled on the first row, instead of once per row
+    #[graphql(skip)]
+    regex: OnceLock<Option<Regex>>,
+    #[graphql(skip)]
+    iregex: OnceLock<Option<Regex>>,
 }
 
 #[derive(Debug, InputObject)]
@@ -57,23 +63,40 @@ pub struct FloatFilter {
     pub _nin: Option<Vec<f64>>,
 }
 
-// same as StringFilter
 #[derive(Debug, InputObject)]
 pub struct ModifierFilter {
-    pub _eq: Option<String>,
-    pub _ieq: Option<String>,
-    pub _ne: Option<String>,
-    pub _ine: Option<String>,
-    pub _contains: Option<String>,
-    pub _icontains: Option<String>,
-    pub _startswith: Option<String>,
-    pub _istartswith: Option<String>,
-    pub _endswith: Option<String>,
-    pub _iendswith: Option<String>,
-    pub _regex: Option<String>,
-    pub _iregex: Option<String>,
-    pub _in: Option<Vec<String>>,
-    pub _nin: Option<Vec<String>>,
+    /// filters on the modifier text
+    #[graphql(flatten)]
+    pub text: StringFilter,
+    /// filters on the text with numeric values replaced by #
+    pub _template: Option<StringFilter>,
+    pub _values: Option<ModifierValueFilter>,
+}
+
+#[derive(Debug, InputObject)]
+pub struct ModifierValueFilter {
+    /// position of the value within the modifier, any value may match if omitted
+    pub _index: Option<i32>,
+    pub _min: Option<FloatFilter>,
+    pub _max: Option<FloatFilter>,
+}
+
+/// quantified filters for list fields, each element is checked with the inner filter
+#[derive(Debug, InputObject)]
+#[graphql(concrete(name = "StringListFilter", params(StringFilter)))]
+#[graphql(concrete(name = "IntListFilter", params(IntFilter)))]
+#[graphql(concrete(name = "FloatListFilter", params(FloatFilter)))]
+#[graphql(concrete(name = "BooleanListFilter", params(BooleanFilter)))]
+#[graphql(concrete(name = "ModifierListFilter", params(ModifierFilter)))]
+pub struct ListFilter<F: InputType> {
+    /// at least one element matches
+    pub _any: Option<F>,
+    /// every element matches, true for empty lists
+    pub _all: Option<F>,
+    /// no element matches
+    pub _none: Option<F>,
+    /// number of elements
+    pub _size: Option<IntFilter>,
 }
 
 pub trait FilterInput {
@@ -94,6 +117,11 @@ impl FilterInput for BooleanFilter {
     }
 }
 
+/// the pattern compiled once, `None` if it does not compile
+fn compiled(cell: &OnceLock<Option<Regex>>, pattern: impl FnOnce() -> String) -> Option<&Regex> {
+    cell.get_or_init(|| Regex::new(&pattern()).ok()).as_ref()
+}
+
 impl FilterInput for StringFilter {
     type Item = String;
 
@@ -128,12 +156,19 @@ impl FilterInput for StringFilter {
                 _iendswith: Some(v),
                 ..
             } if !sl.ends_with(v) => false,
+            // patterns are validated when parsed, one that still fails to compile matches nothing
             Self {
-                _regex: Some(v), ..
-            } if !Regex::new(v).unwrap().is_match(&s) => false,
+                _regex: Some(v),
+                regex,
+                ..
+            } if !compiled(regex, || v.clone()).is_some_and(|re| re.is_match(&s)) => false,
             Self {
-                _iregex: Some(v), ..
-            } if !Regex::new(&format!("(?i){}", v)).unwrap().is_match(&s) => false,
+                _iregex: Some(v),
+                iregex,
+                ..
+            } if !compiled(iregex, || format!("(?i){v}")).is_some_and(|re| re.is_match(&s)) => {
+                false
+            }
             Self { _in: Some(v), .. } if !v.contains(&s) => false,
             Self { _nin: Some(v), .. } if v.contains(&s) => false,
             _ => true,
@@ -181,24 +216,69 @@ impl FilterInput for ModifierFilter {
     type Item = Modifier;
 
     fn filter_fn(&self, s: Self::Item) -> bool {
-        let string_fitler = StringFilter {
-            _eq: self._eq.clone(),
-            _ieq: self._ieq.clone(),
-            _ne: self._ne.clone(),
-            _ine: self._ine.clone(),
-            _contains: self._contains.clone(),
-            _icontains: self._icontains.clone(),
-            _startswith: self._startswith.clone(),
-            _istartswith: self._istartswith.clone(),
-            _endswith: self._endswith.clone(),
-            _iendswith: self._iendswith.clone(),
-            _regex: self._regex.clone(),
-            _iregex: self._iregex.clone(),
-            _in: self._in.clone(),
-            _nin: self._nin.clone(),
-        };
+        let Self {
+            text,
+            _template: template,
+            _values: values,
+        } = self;
+        if !text.filter_fn(s.text) {
+            return false;
+        }
+
+        if let Some(template_filter) = template {
+            if !template_filter.filter_fn(s.template) {
+                return false;
+            }
+        }
+
+        if let Some(values_filter) = values {
+            let ModifierValueFilter { _index: index, .. } = values_filter;
+            return match *index {
+                Some(index) => usize::try_from(index)
+                    .ok()
+                    .and_then(|index| s.values.get(index))
+                    .is_some_and(|value| values_filter.filter_fn(*value)),
+                None => s.values.iter().any(|value| values_filter.filter_fn(*value)),
+            };
+        }
+
+        true
+    }
+}
+
+impl FilterInput for ModifierValueFilter {
+    type Item = ModifierValue;
 
-        string_fitler.filter_fn(s.text)
+    fn filter_fn(&self, s: Self::Item) -> bool {
+        match &self {
+            Self { _min: Some(v), .. } if !v.filter_fn(s.min) => false,
+            Self { _max: Some(v), .. } if !v.filter_fn(s.max) => false,
+            _ => true,
+        }
+    }
+}
+
+impl<F> FilterInput for ListFilter<F>
+where
+    F: FilterInput + InputType,
+    F::Item: Clone,
+{
+    type Item = Vec<F::Item>;
+
+    fn filter_fn(&self, s: Self::Item) -> bool {
+        let matches = |f: &F, v: &F::Item| f.filter_fn(v.clone());
+
+        match self {
+            Self { _any: Some(f), .. } if !s.iter().any(|v| matches(f, v)) => false,
+            Self { _all: Some(f), .. } if !s.iter().all(|v| matches(f, v)) => false,
+            Self { _none: Some(f), .. } if s.iter().any(|v| matches(f, v)) => false,
+            Self { _size: Some(f), .. }
+                if !f.filter_fn(i32::try_from(s.len()).unwrap_or(i32::MAX)) =>
+            {
+                false
+            }
+            _ => true,
+        }
     }
 }
 
@@ -206,89 +286,161 @@ pub trait WhereInput
 where
     Self: Sized,
 {
-    type Output: Clone + Hash + Eq;
+    type Output: Clone;
 
     // boilerplate required to access where and, or, not struct fields
     fn and(&self) -> Option<&Vec<Self>>;
     fn or(&self) -> Option<&Vec<Self>>;
     fn not(&self) -> Option<&Vec<Self>>;
 
-    fn filter(&self, arr: Vec<Self::Output>) -> Vec<Self::Output>;
+    /// checks the field filters against a single item, ignoring and, or, not
+    fn matches(&self, item: &Self::Output) -> bool;
+
+    /// checks a single item including the recursive and, or, not filters
+    fn matches_recursive(&self, item: &Self::Output) -> bool {
+        self.matches(item)
+            && self
+                .and()
+                .is_none_or(|and| and.iter().all(|inner| inner.matches_recursive(item)))
+            && self
+                .or()
+                .is_none_or(|or| or.iter().any(|inner| inner.matches_recursive(item)))
+            && !self
+                .not()
+                .is_some_and(|not| not.iter().any(|inner| inner.matches_recursive(item)))
+    }
 
-    fn filter_recursive(&self, arr: &[Self::Output]) -> Vec<Self::Output> {
-        let mut filtered = self.filter(arr.to_vec());
+    fn filter(&self, arr: Vec<Self::Output>) -> Vec<Self::Output> {
+        arr.into_iter().filter(|item| self.matches(item)).collect()
+    }
 
-        if let Some(and) = self.and() {
-            filtered = and
-                .iter()
-                .fold(filtered, |acc, inner| inner.filter_recursive(&acc))
-        }
+    /// the matching items, each checked once against the whole tree of filters
+    fn filter_recursive(&self, arr: &[Self::Output]) -> Vec<Self::Output> {
+        arr.iter()
+            .filter(|item| self.matches_recursive(item))
+            .cloned()
+            .collect()
+    }
+}
 
-        if let Some(or) = self.or() {
-            filtered = or
-                .iter()
-                .fold(HashSet::new(), |mut acc, inner| {
-                    acc.extend(inner.filter_recursive(&filtered));
-                    acc
-                })
-                .into_iter()
-                .collect();
-        }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
 
-        if let Some(not) = self.not() {
-            let all_matching = not.iter().fold(HashSet::new(), |mut acc, inner| {
-                acc.extend(inner.filter_recursive(&filtered));
-                acc
-            });
-            let all_matching: HashSet<_> = all_matching.iter().collect();
+    use super::WhereInput;
+    use crate::schema::{
+        fixtures::{input, items, names},
+        ninja_common::Sparkline,
+        ninja_item::{Item, ItemWhere},
+    };
 
-            filtered.retain(|item| !all_matching.contains(item))
-        }
+    fn filter(value: serde_json::Value) -> Vec<Item> {
+        input::<ItemWhere>(value).filter_recursive(&items())
+    }
 
-        filtered
+    #[test]
+    fn string_filter() {
+        assert_eq!(
+            names(&filter(json!({"name": {"eq": "Mageblood"}}))),
+            ["Mageblood"]
+        );
+        assert_eq!(filter(json!({"name": {"icontains": "MAGEBLOOD"}})).len(), 1);
+        assert!(filter(json!({"name": {"eq": "mageblood"}})).is_empty());
     }
-}
 
-#[derive(Debug, InputObject)]
-pub struct ItemEndpointFilter {
-    _eq: Option<ItemEndpoint>,
-    _ne: Option<ItemEndpoint>,
-    _in: Option<Vec<ItemEndpoint>>,
-    _nin: Option<Vec<ItemEndpoint>>,
-}
+    #[test]
+    fn float_filter() {
+        let expensive = filter(json!({"chaosValue": {"gt": 30000.0}}));
+        let mut expensive = names(&expensive);
+        expensive.sort_unstable();
+        assert_eq!(expensive, ["Headhunter", "Mageblood"]);
+    }
 
-impl FilterInput for ItemEndpointFilter {
-    type Item = ItemEndpoint;
+    #[test]
+    fn list_filter() {
+        assert_eq!(
+            filter(json!({"explicitModifiers": {"size": {"eq": 6}}})).len(),
+            49
+        );
+        assert_eq!(
+            filter(json!({"explicitModifiers": {"none": {"contains": "Resistance"}}})).len(),
+            142
+        );
+
+        let all = filter(json!({"explicitModifiers": {"all": {"contains": "Resistance"}}}));
+        assert!(all.iter().all(|item| item
+            .explicit_modifiers
+            .iter()
+            .all(|modifier| modifier.text.contains("Resistance"))));
+    }
 
-    fn filter_fn(&self, s: Self::Item) -> bool {
-        match &self {
-            Self { _eq: Some(v), .. } if &s != v => false,
-            Self { _ne: Some(v), .. } if &s == v => false,
-            Self { _in: Some(v), .. } if !v.contains(&s) => false,
-            Self { _nin: Some(v), .. } if v.contains(&s) => false,
-            _ => true,
-        }
+    #[test]
+    fn list_of_optional_values() {
+        let falling = filter(json!({"sparkline": {"data": {"any": {"lt": -10.0}}}}));
+        assert!(!falling.is_empty());
+        assert!(falling.iter().all(|item| item
+            .sparkline
+            .data
+            .iter()
+            .flatten()
+            .any(|value| *value < -10.0)));
+
+        // days without data are skipped
+        let item = Item {
+            sparkline: Sparkline {
+                data: vec![None, Some(2.5), None],
+                total_change: 2.5,
+            },
+            ..Item::default()
+        };
+        let matches = |value| input::<ItemWhere>(value).matches_recursive(&item);
+        assert!(matches(
+            json!({"sparkline": {"data": {"all": {"gt": 0.0}}}})
+        ));
+        assert!(matches(json!({"sparkline": {"data": {"size": {"eq": 1}}}})));
     }
-}
 
-#[derive(Debug, InputObject)]
-pub struct CurrencyEndpointFilter {
-    _eq: Option<CurrencyEndpoint>,
-    _ne: Option<CurrencyEndpoint>,
-    _in: Option<Vec<CurrencyEndpoint>>,
-    _nin: Option<Vec<CurrencyEndpoint>>,
-}
+    #[test]
+    fn modifier_values() {
+        let dexterity = filter(json!({
+            "explicitModifiers": {"any": {"template": {"eq": "# to Dexterity"}}}
+        }));
+        assert_eq!(dexterity.len(), 22);
+
+        let high_roll = filter(json!({
+            "name": {"eq": "Mageblood"},
+            "explicitModifiers": {"any": {
+                "template": {"eq": "# to Dexterity"},
+                "values": {"index": 0, "min": {"eq": 30.0}, "max": {"eq": 60.0}},
+            }},
+        }));
+        assert_eq!(names(&high_roll), ["Mageblood"]);
+    }
 
-impl FilterInput for CurrencyEndpointFilter {
-    type Item = CurrencyEndpoint;
+    #[test]
+    fn nested_filter() {
+        let rising = filter(json!({
+            "sparkline": {"totalChange": {"gt": 20.0}},
+            "listingCount": {"gt": 50},
+        }));
+        assert_eq!(rising.len(), 32);
+    }
 
-    fn filter_fn(&self, s: Self::Item) -> bool {
-        match &self {
-            Self { _eq: Some(v), .. } if &s != v => false,
-            Self { _ne: Some(v), .. } if &s == v => false,
-            Self { _in: Some(v), .. } if !v.contains(&s) => false,
-            Self { _nin: Some(v), .. } if v.contains(&s) => false,
-            _ => true,
-        }
+    #[test]
+    fn recursive_filters() {
+        let either = filter(json!({
+            "or": [{"name": {"eq": "Mageblood"}}, {"name": {"eq": "Headhunter"}}]
+        }));
+        assert_eq!(either.len(), 2);
+
+        let neither = filter(json!({
+            "not": [{"name": {"eq": "Mageblood"}}, {"name": {"eq": "Headhunter"}}]
+        }));
+        assert_eq!(neither.len(), items().len() - 2);
+
+        let both = filter(json!({
+            "and": [{"name": {"eq": "Mageblood"}}, {"name": {"eq": "Headhunter"}}]
+        }));
+        assert!(both.is_empty());
     }
 }
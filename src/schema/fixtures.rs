@@ -0,0 +1,29 @@
+use async_graphql::InputType;
+
+use super::ninja_item::{Item, ItemRaw, Modifier};
+
+/// unique jewelry lines recorded from poe.ninja
+pub fn items() -> Vec<Item> {
+    let mut items = serde_json::from_str::<ItemRaw>(include_str!("jewelry.json"))
+        .expect("failed to parse jewelry.json")
+        .lines;
+
+    items.iter_mut().for_each(|item| {
+        item.implicit_modifiers
+            .iter_mut()
+            .chain(item.explicit_modifiers.iter_mut())
+            .for_each(Modifier::parse_values);
+    });
+
+    items
+}
+
+/// parses a graphql input object from json, as it would be received in a query
+pub fn input<T: InputType + std::fmt::Debug>(value: serde_json::Value) -> T {
+    let value = async_graphql::Value::from_json(value).expect("invalid graphql value");
+    T::parse(Some(value)).expect("invalid graphql input")
+}
+
+pub fn names(items: &[Item]) -> Vec<&str> {
+    items.iter().map(|item| item.name.as_str()).collect()
+}
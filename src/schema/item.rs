@@ -1,76 +1,70 @@
-use super::cache::fetch_with_cache;
+use super::cache::{fetch_with_cache, refetch};
 use super::filters::WhereInput;
 use super::ninja_common::League;
-use super::ninja_item::{Item, ItemEndpoint, ItemOrderby, ItemRaw, ItemWhere};
+use super::ninja_item::{Item, ItemEndpoint, ItemOrderby, ItemRaw, ItemWhere, Modifier};
 use super::orderby::OrderbyInput;
+use super::upstream::{fetch_overview, FetchError, Overview};
 use futures::future;
+use std::sync::Arc;
 
-async fn fetch_item_endpoint(league: League, endpoint: &ItemEndpoint) -> ItemRaw {
-    let endpoint_str = endpoint.to_string();
-    let url = format!(
-        "https://poe.ninja/api/data/itemoverview?league={}&type={}",
-        league.to_string(),
-        endpoint_str
-    );
-    let mut items = reqwest::get(url)
-        .await
-        .unwrap_or_else(|_| panic!("could not fetch item data from endpoint: {}", endpoint_str))
-        .json::<ItemRaw>()
-        .await
-        .unwrap_or_else(|_| panic!("could not parse item data from endpoint: {}", endpoint_str));
+async fn fetch_item_endpoint(
+    league: League,
+    endpoint: &ItemEndpoint,
+) -> Result<ItemRaw, FetchError> {
+    let mut items: ItemRaw = fetch_overview(Overview::Item, league, &endpoint.to_string()).await?;
 
     // add endpoint information
     items.lines.iter_mut().for_each(|line| {
         line.endpoint = *endpoint;
     });
-    items
+    Ok(items)
 }
 
-async fn fetch_items(league: League) -> Vec<Item> {
-    // let items: ItemRaw =
-    //     serde_json::from_str(include_str!("jewelry.json")).expect("failed to parse jewelry.json");
-
-    let responses = future::join_all(
-        [
-            // General
-            ItemEndpoint::Tattoo,
-            ItemEndpoint::Omen,
-            ItemEndpoint::DivinationCard,
-            ItemEndpoint::Artifact,
-            ItemEndpoint::Oil,
-            ItemEndpoint::Incubator,
-            // Equipment & Gems
-            ItemEndpoint::UniqueWeapon,
-            ItemEndpoint::UniqueArmour,
-            ItemEndpoint::UniqueAccessory,
-            ItemEndpoint::UniqueFlask,
-            ItemEndpoint::UniqueJewel,
-            ItemEndpoint::UniqueRelic,
-            ItemEndpoint::SkillGem,
-            ItemEndpoint::ClusterJewel,
-            // Atlas
-            ItemEndpoint::Map,
-            ItemEndpoint::BlightedMap,
-            ItemEndpoint::BlightRavagedMap,
-            ItemEndpoint::ScourgedMap,
-            ItemEndpoint::UniqueMap,
-            ItemEndpoint::DeliriumOrb,
-            ItemEndpoint::Invitation,
-            ItemEndpoint::Scarab,
-            ItemEndpoint::Memory,
-            // Crafting
-            ItemEndpoint::BaseType,
-            ItemEndpoint::Fossil,
-            ItemEndpoint::Resonator,
-            ItemEndpoint::HelmetEnchant,
-            ItemEndpoint::Beast,
-            ItemEndpoint::Essence,
-            ItemEndpoint::Vial,
-        ]
-        .iter()
-        .map(|endpoint| async move { fetch_item_endpoint(league, endpoint).await }),
+/// every endpoint that makes up the items of a league
+pub const ITEM_ENDPOINTS: [ItemEndpoint; 30] = [
+    // General
+    ItemEndpoint::Tattoo,
+    ItemEndpoint::Omen,
+    ItemEndpoint::DivinationCard,
+    ItemEndpoint::Artifact,
+    ItemEndpoint::Oil,
+    ItemEndpoint::Incubator,
+    // Equipment & Gems
+    ItemEndpoint::UniqueWeapon,
+    ItemEndpoint::UniqueArmour,
+    ItemEndpoint::UniqueAccessory,
+    ItemEndpoint::UniqueFlask,
+    ItemEndpoint::UniqueJewel,
+    ItemEndpoint::UniqueRelic,
+    ItemEndpoint::SkillGem,
+    ItemEndpoint::ClusterJewel,
+    // Atlas
+    ItemEndpoint::Map,
+    ItemEndpoint::BlightedMap,
+    ItemEndpoint::BlightRavagedMap,
+    ItemEndpoint::ScourgedMap,
+    ItemEndpoint::UniqueMap,
+    ItemEndpoint::DeliriumOrb,
+    ItemEndpoint::Invitation,
+    ItemEndpoint::Scarab,
+    ItemEndpoint::Memory,
+    // Crafting
+    ItemEndpoint::BaseType,
+    ItemEndpoint::Fossil,
+    ItemEndpoint::Resonator,
+    ItemEndpoint::HelmetEnchant,
+    ItemEndpoint::Beast,
+    ItemEndpoint::Essence,
+    ItemEndpoint::Vial,
+];
+
+async fn fetch_items(league: League) -> Result<Vec<Item>, FetchError> {
+    let responses = future::try_join_all(
+        ITEM_ENDPOINTS
+            .iter()
+            .map(|endpoint| async move { fetch_item_endpoint(league, endpoint).await }),
     )
-    .await;
+    .await?;
 
     let mut items = responses
         .into_iter()
@@ -94,30 +88,50 @@ async fn fetch_items(league: League) -> Vec<Item> {
         // }
 
         item.name = name;
+
+        item.implicit_modifiers
+            .iter_mut()
+            .chain(item.explicit_modifiers.iter_mut())
+            .for_each(Modifier::parse_values);
     });
 
-    items.lines
+    Ok(items.lines)
+}
+
+/// all items of the league, from the cache if it is fresh
+pub async fn load_items(league: League) -> Result<Arc<Vec<Item>>, FetchError> {
+    let endpoints = ITEM_ENDPOINTS.map(|endpoint| endpoint.to_string());
+    fetch_with_cache("item", league, &endpoints, || async {
+        fetch_items(league).await
+    })
+    .await
+}
+
+/// all items of the league fetched again, the cache is kept if that fails
+pub async fn refetch_items(league: League) -> Result<Arc<Vec<Item>>, FetchError> {
+    let endpoints = ITEM_ENDPOINTS.map(|endpoint| endpoint.to_string());
+    refetch("item", league, &endpoints, || async {
+        fetch_items(league).await
+    })
+    .await
 }
 
 pub async fn get_items(
-    _where: Option<ItemWhere>,
-    _orderby: Vec<ItemOrderby>,
+    filter: Option<ItemWhere>,
+    orderby: Vec<ItemOrderby>,
     league: Option<League>,
-) -> Vec<Item> {
+) -> Result<Vec<Item>, FetchError> {
     let league = league.unwrap_or(League::TmpStandard);
 
-    let items = fetch_with_cache("item", league, || async { fetch_items(league).await })
-        .await
-        // error will be bubbled up
-        .unwrap();
+    let items = load_items(league).await?;
 
-    let mut items = if let Some(_where) = _where {
-        _where.filter_recursive(&items)
+    let mut items = if let Some(filter) = filter {
+        filter.filter_recursive(&items)
     } else {
-        items
+        Vec::clone(&items)
     };
 
-    ItemOrderby::orderby(&mut items, _orderby);
+    ItemOrderby::orderby(&mut items, orderby);
 
-    items
+    Ok(items)
 }
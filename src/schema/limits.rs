@@ -0,0 +1,312 @@
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery},
+    parser::types::{ExecutableDocument, Selection, SelectionSet},
+    CustomValidator, InputValueError, Positioned, ServerError, ServerResult, SimpleObject, Value,
+    Variables,
+};
+use regex::Regex;
+use std::{
+    convert::Infallible,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+const DEFAULT_LIMITS: Limits = Limits {
+    depth: 10,
+    complexity: 500,
+    where_depth: 8,
+    regex_length: 256,
+    results: 5000,
+};
+
+/// bounds on what a single query may ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::Args)]
+pub struct Limits {
+    /// deepest nesting of fields in a graphql query
+    #[arg(long = "max-depth", global = true, default_value_t = DEFAULT_LIMITS.depth)]
+    pub depth: usize,
+    /// most fields a graphql query may select, each counting 1
+    #[arg(long = "max-complexity", global = true, default_value_t = DEFAULT_LIMITS.complexity)]
+    pub complexity: usize,
+    /// deepest nesting of and, or and not in a where filter
+    #[arg(long = "max-where-depth", global = true, default_value_t = DEFAULT_LIMITS.where_depth)]
+    pub where_depth: usize,
+    /// longest regex and iregex pattern
+    #[arg(long = "max-regex-length", global = true, default_value_t = DEFAULT_LIMITS.regex_length)]
+    pub regex_length: usize,
+    /// most rows a list query may return, larger results have to be paged
+    #[arg(long = "max-results", global = true, default_value_t = DEFAULT_LIMITS.results)]
+    pub results: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        DEFAULT_LIMITS
+    }
+}
+
+static LIMITS: RwLock<Limits> = RwLock::new(DEFAULT_LIMITS);
+
+/// applies to queries from now on, and to the depth and complexity of schemas built
+/// from now on
+pub fn set_limits(limits: Limits) {
+    *LIMITS.write().expect("limits lock poisoned") = limits;
+}
+
+pub fn limits() -> Limits {
+    *LIMITS.read().expect("limits lock poisoned")
+}
+
+/// requests left to the api key or address of a request, counted per minute
+#[derive(Debug, Clone, PartialEq, Eq, SimpleObject)]
+pub struct Quota {
+    /// name of the api key, absent for anonymous requests counted per address
+    pub key: Option<String>,
+    /// requests per minute
+    pub limit: u32,
+    /// requests left this minute, the current one counted
+    pub remaining: u32,
+    /// seconds until the count starts over
+    pub resets_in: u64,
+}
+
+/// a query exceeding the [`Limits`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    WhereTooDeep { depth: usize, max: usize },
+    TooManyResults { rows: usize, max: usize },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WhereTooDeep { depth, max } => write!(
+                f,
+                "where filter is nested {depth} levels deep, more than the limit of {max}"
+            ),
+            Self::TooManyResults { rows, max } => write!(
+                f,
+                "query returns {rows} rows, more than the limit of {max}. \
+                 narrow it down with where, or page through it with limit and offset"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// levels of nested and, or, not in a where filter, 1 without any.
+/// the filters of nested models count their own levels
+fn where_depth(value: &Value) -> usize {
+    match value {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(name, value)| match name.as_str() {
+                "and" | "or" | "not" => 1 + where_depth(value),
+                _ => where_depth(value),
+            })
+            .max()
+            .unwrap_or_default()
+            .max(1),
+        Value::List(values) => values.iter().map(where_depth).max().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// checks a where filter before it is parsed, as parsing recurses once per level
+pub fn check_where(filter: &Value) -> Result<(), LimitError> {
+    let max = limits().where_depth;
+    match where_depth(filter) {
+        depth if depth > max => Err(LimitError::WhereTooDeep { depth, max }),
+        _ => Ok(()),
+    }
+}
+
+/// the rows from `offset` on, at most `limit` of them, unless they exceed the result limit
+pub fn page<T>(
+    rows: Vec<T>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Vec<T>, LimitError> {
+    let rows: Vec<_> = rows
+        .into_iter()
+        .skip(offset.unwrap_or_default())
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    let max = limits().results;
+    if rows.len() > max {
+        return Err(LimitError::TooManyResults {
+            rows: rows.len(),
+            max,
+        });
+    }
+    Ok(rows)
+}
+
+/// rejects patterns that are too long or do not compile
+pub struct RegexValidator;
+
+impl CustomValidator<String> for RegexValidator {
+    fn check(&self, value: &String) -> Result<(), InputValueError<String>> {
+        let max = limits().regex_length;
+        let length = value.chars().count();
+        if length > max {
+            return Err(InputValueError::custom(format!(
+                "regex is {length} characters long, more than the limit of {max}"
+            )));
+        }
+        Regex::new(value)
+            .map(drop)
+            .map_err(|err| InputValueError::custom(format!("invalid regex: {err}")))
+    }
+}
+
+/// rejects queries with where arguments nested deeper than the limit, before they are
+/// parsed into filters
+pub struct WhereLimit;
+
+impl ExtensionFactory for WhereLimit {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(WhereLimitExtension)
+    }
+}
+
+struct WhereLimitExtension;
+
+#[async_trait::async_trait]
+impl Extension for WhereLimitExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let selection_sets = document
+            .operations
+            .iter()
+            .map(|(_, operation)| &operation.node.selection_set)
+            .chain(
+                document
+                    .fragments
+                    .values()
+                    .map(|fragment| &fragment.node.selection_set),
+            );
+        for selection_set in selection_sets {
+            check_selection_set(selection_set, variables)?;
+        }
+        Ok(document)
+    }
+}
+
+fn check_selection_set(
+    selection_set: &Positioned<SelectionSet>,
+    variables: &Variables,
+) -> ServerResult<()> {
+    for selection in &selection_set.node.items {
+        match &selection.node {
+            Selection::Field(field) => {
+                for (name, value) in &field.node.arguments {
+                    if name.node != "where" {
+                        continue;
+                    }
+                    let filter = value
+                        .node
+                        .clone()
+                        .into_const_with(|name| {
+                            Ok::<_, Infallible>(variables.get(&name).cloned().unwrap_or_default())
+                        })
+                        .unwrap_or_else(|never| match never {});
+                    check_where(&filter)
+                        .map_err(|err| ServerError::new(err.to_string(), Some(value.pos)))?;
+                }
+                check_selection_set(&field.node.selection_set, variables)?;
+            }
+            Selection::InlineFragment(fragment) => {
+                check_selection_set(&fragment.node.selection_set, variables)?;
+            }
+            // fragments are checked on their own
+            Selection::FragmentSpread(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{check_where, page, LimitError, DEFAULT_LIMITS};
+    use crate::schema::ninja_item::ItemWhere;
+
+    /// a where filter with `depth` levels of not
+    fn nested(depth: usize) -> async_graphql::Value {
+        let filter = (1..depth).fold(
+            json!({"name": {"eq": "Mageblood"}}),
+            |inner, _| json!({ "not": [inner] }),
+        );
+        async_graphql::Value::from_json(filter).expect("value")
+    }
+
+    #[test]
+    fn where_depth() {
+        let max = DEFAULT_LIMITS.where_depth;
+        assert_eq!(check_where(&nested(max)), Ok(()));
+        assert_eq!(
+            check_where(&nested(max + 1)),
+            Err(LimitError::WhereTooDeep {
+                depth: max + 1,
+                max
+            })
+        );
+
+        let depth =
+            |filter| super::where_depth(&async_graphql::Value::from_json(filter).expect("value"));
+        assert_eq!(depth(json!({})), 1);
+        assert_eq!(
+            depth(
+                json!({"or": [{"name": {"eq": "Mageblood"}}, {"and": [{"name": {"eq": "Headhunter"}}]}]})
+            ),
+            3
+        );
+        // nested models and single filters in place of lists
+        assert_eq!(depth(json!({"sparkline": {"not": {"not": {}}}})), 3);
+    }
+
+    #[test]
+    fn paging() {
+        let rows: Vec<_> = (0..10).collect();
+        assert_eq!(page(rows.clone(), Some(8), None), Ok(vec![8, 9]));
+        assert_eq!(page(rows.clone(), Some(2), Some(3)), Ok(vec![2, 3, 4]));
+        assert_eq!(page(rows, Some(20), Some(3)), Ok(vec![]));
+
+        let max = DEFAULT_LIMITS.results;
+        let too_many = vec![0; max + 1];
+        assert_eq!(
+            page(too_many.clone(), None, None),
+            Err(LimitError::TooManyResults { rows: max + 1, max })
+        );
+        assert_eq!(
+            page(too_many, Some(1), None).map(|rows| rows.len()),
+            Ok(max)
+        );
+    }
+
+    #[test]
+    fn regex_validation() {
+        let parse = |regex: &str| {
+            async_graphql::InputType::parse(Some(
+                async_graphql::Value::from_json(json!({"name": {"regex": regex}})).expect("value"),
+            ))
+            .map(|_: ItemWhere| ())
+            .map_err(|err| err.into_server_error(async_graphql::Pos::default()).message)
+        };
+
+        assert_eq!(parse("^Mage(blood)?$"), Ok(()));
+        assert!(parse("(unclosed").is_err_and(|err| err.contains("invalid regex")));
+        let long = "a".repeat(DEFAULT_LIMITS.regex_length + 1);
+        assert!(parse(&long).is_err_and(|err| err.contains("more than the limit of 256")));
+    }
+}
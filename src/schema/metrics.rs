@@ -0,0 +1,298 @@
+use async_graphql::{
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextPrepareRequest,
+        NextRequest, NextResolve, ResolveInfo,
+    },
+    parser::types::{ExecutableDocument, OperationType},
+    EnumType, Request, Response, ServerResult, Value, Variables,
+};
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder, DEFAULT_BUCKETS,
+};
+use std::{
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::cache::{remembered, timestamp};
+use super::ninja_common::League;
+
+/// content type of [`render_metrics`]
+pub const METRICS_CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn counter(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+    REGISTRY
+        .register(Box::new(counter.clone()))
+        .expect("unique metric name");
+    counter
+}
+
+fn histogram(name: &str, help: &str, labels: &[&str], buckets: Vec<f64>) -> HistogramVec {
+    let opts = HistogramOpts::new(name, help).buckets(buckets);
+    let histogram = HistogramVec::new(opts, labels).expect("valid histogram");
+    REGISTRY
+        .register(Box::new(histogram.clone()))
+        .expect("unique metric name");
+    histogram
+}
+
+fn gauge(name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    let gauge = IntGaugeVec::new(Opts::new(name, help), labels).expect("valid gauge");
+    REGISTRY
+        .register(Box::new(gauge.clone()))
+        .expect("unique metric name");
+    gauge
+}
+
+static OPERATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter(
+        "poe_api_graphql_operations_total",
+        "graphql operations by type and outcome",
+        &["type", "status"],
+    )
+});
+
+static OPERATION_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram(
+        "poe_api_graphql_operation_duration_seconds",
+        "time to answer a graphql operation",
+        &["type"],
+        DEFAULT_BUCKETS.to_vec(),
+    )
+});
+
+static RESOLVER_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram(
+        "poe_api_graphql_resolver_duration_seconds",
+        "time to resolve a query or mutation field",
+        &["field"],
+        DEFAULT_BUCKETS.to_vec(),
+    )
+});
+
+static ROWS_RETURNED: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram(
+        "poe_api_graphql_rows_returned",
+        "rows returned by a query or mutation field",
+        &["field"],
+        exponential_buckets(1.0, 4.0, 8).expect("valid buckets"),
+    )
+});
+
+static UPSTREAM_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter(
+        "poe_api_upstream_requests_total",
+        "poe.ninja requests by endpoint and status, error when there was no response",
+        &["overview", "endpoint", "status"],
+    )
+});
+
+static UPSTREAM_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram(
+        "poe_api_upstream_request_duration_seconds",
+        "time of a poe.ninja request, retries included",
+        &["overview", "endpoint"],
+        DEFAULT_BUCKETS.to_vec(),
+    )
+});
+
+static CACHE_HITS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter(
+        "poe_api_cache_hits_total",
+        "fresh data found in memory or on disk",
+        &["kind", "layer"],
+    )
+});
+
+static CACHE_MISSES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter(
+        "poe_api_cache_misses_total",
+        "data fetched because it was missing, stale or invalid",
+        &["kind"],
+    )
+});
+
+static CACHE_AGE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    gauge(
+        "poe_api_cache_age_seconds",
+        "seconds since the data in memory was fetched",
+        &["kind", "league"],
+    )
+});
+
+static REJECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter(
+        "poe_api_rejected_requests_total",
+        "requests rejected for a missing or invalid api key or over the rate limit",
+        &["reason"],
+    )
+});
+
+pub fn record_upstream(overview: &str, endpoint: &str, status: &str, elapsed: Duration) {
+    UPSTREAM_REQUESTS
+        .with_label_values(&[overview, endpoint, status])
+        .inc();
+    UPSTREAM_SECONDS
+        .with_label_values(&[overview, endpoint])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// `layer` is memory or disk
+pub fn record_cache_hit(kind: &str, layer: &str) {
+    CACHE_HITS.with_label_values(&[kind, layer]).inc();
+}
+
+pub fn record_cache_miss(kind: &str) {
+    CACHE_MISSES.with_label_values(&[kind]).inc();
+}
+
+/// `reason` is `unauthorized` or `rate_limited`
+pub fn record_rejected(reason: &str) {
+    REJECTED.with_label_values(&[reason]).inc();
+}
+
+/// graphql name of the league, as in the api
+fn league_name(league: League) -> &'static str {
+    League::items()
+        .iter()
+        .find(|item| item.value == league)
+        .map_or("", |item| item.name)
+}
+
+/// every metric in the prometheus text format
+pub fn render_metrics() -> String {
+    let now = timestamp();
+    CACHE_AGE.reset();
+    for (kind, league, fetch_time) in remembered() {
+        let age = i64::try_from(now.saturating_sub(fetch_time)).unwrap_or(i64::MAX);
+        CACHE_AGE
+            .with_label_values(&[&kind, league_name(league)])
+            .set(age);
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("metrics are encodable");
+    String::from_utf8(buffer).expect("metrics are utf-8")
+}
+
+/// records graphql operations, the latency of the query and mutation fields
+/// and how many rows they return
+pub struct Metrics;
+
+impl ExtensionFactory for Metrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(MetricsExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct MetricsExtension {
+    operation_name: Mutex<Option<String>>,
+    /// operations of the document by name, empty until it is parsed
+    operations: Mutex<Vec<(Option<String>, OperationType)>>,
+}
+
+impl MetricsExtension {
+    /// type of the executed operation, unknown if the document did not parse
+    fn operation_type(&self) -> &'static str {
+        let name = self
+            .operation_name
+            .lock()
+            .expect("metrics lock poisoned")
+            .clone();
+        let operations = self.operations.lock().expect("metrics lock poisoned");
+        let operation = match operations.as_slice() {
+            [(_, ty)] => Some(*ty),
+            operations => operations
+                .iter()
+                .find(|(operation, _)| *operation == name)
+                .map(|(_, ty)| *ty),
+        };
+        drop(operations);
+
+        match operation {
+            Some(OperationType::Query) => "query",
+            Some(OperationType::Mutation) => "mutation",
+            Some(OperationType::Subscription) => "subscription",
+            None => "unknown",
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for MetricsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let start = Instant::now();
+        let response = next.run(ctx).await;
+
+        let ty = self.operation_type();
+        let status = if response.is_ok() { "ok" } else { "error" };
+        OPERATIONS.with_label_values(&[ty, status]).inc();
+        OPERATION_SECONDS
+            .with_label_values(&[ty])
+            .observe(start.elapsed().as_secs_f64());
+        response
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        self.operation_name
+            .lock()
+            .expect("metrics lock poisoned")
+            .clone_from(&request.operation_name);
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        *self.operations.lock().expect("metrics lock poisoned") = document
+            .operations
+            .iter()
+            .map(|(name, operation)| (name.map(ToString::to_string), operation.node.ty))
+            .collect();
+        Ok(document)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        // nested fields are read from the resolved rows, only the root fields are timed
+        if info.path_node.parent.is_some() {
+            return next.run(ctx, info).await;
+        }
+
+        let field = format!("{}.{}", info.parent_type, info.name);
+        let start = Instant::now();
+        let value = next.run(ctx, info).await;
+
+        RESOLVER_SECONDS
+            .with_label_values(&[&field])
+            .observe(start.elapsed().as_secs_f64());
+        if let Ok(Some(Value::List(rows))) = &value {
+            ROWS_RETURNED
+                .with_label_values(&[&field])
+                .observe(rows.len() as f64);
+        }
+        value
+    }
+}
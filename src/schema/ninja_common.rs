@@ -1,23 +1,32 @@
 use async_graphql::{Enum, SimpleObject};
+use poe_api_derive::GQLModel;
 use serde::{Deserialize, Serialize};
 
-use super::{LEAGUE, PREV_LEAGUE};
+use super::{filters::FilterInput, LEAGUE, PREV_LEAGUE};
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject, GQLModel)]
 #[serde(rename_all = "camelCase")]
 pub struct Sparkline {
+    /// daily price changes, null for days without data. filters skip the nulls,
+    /// sorting compares the number of days
+    #[gql(where, orderby)]
     pub data: Vec<Option<f64>>,
+    #[gql(where, orderby)]
     pub total_change: f64,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject, GQLModel)]
 #[serde(rename_all = "camelCase")]
 pub struct SparklineOptional {
+    /// daily price changes, null for days without data. filters skip the nulls,
+    /// sorting compares the number of days
+    #[gql(where, orderby)]
     pub data: Vec<Option<f64>>,
+    #[gql(where, orderby)]
     pub total_change: f64,
 }
 
-#[derive(Default, Debug, Enum, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Enum, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum League {
     #[default]
     TmpStandard,
@@ -1,11 +1,14 @@
 use async_graphql::{Enum, SimpleObject};
-use poe_api_derive::GQLModel;
+use poe_api_derive::{GQLFilter, GQLModel};
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
 
 use super::{
     filters::FilterInput,
-    ninja_common::{Sparkline, SparklineOptional},
+    ninja_common::{
+        Sparkline, SparklineOptional, SparklineOptionalOrderby, SparklineOptionalWhere,
+        SparklineOrderby, SparklineWhere,
+    },
 };
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
@@ -19,10 +22,15 @@ pub struct CurrencyRaw {
 #[serde(rename_all = "camelCase")]
 pub struct Currency {
     pub currency_type_name: String,
+    #[gql(where, orderby, nested)]
     pub pay: Option<Pay>,
+    #[gql(where, orderby, nested)]
     pub receive: Option<Receive>,
+    #[gql(where, orderby, nested)]
     pub pay_spark_line: SparklineOptional,
+    #[gql(where, orderby, nested)]
     pub receive_spark_line: Sparkline,
+    /// price in chaos orbs
     #[serde(rename = "chaosEquivalent")]
     #[gql(where, orderby)]
     pub chaos_value: f64,
@@ -40,15 +48,17 @@ pub struct Currency {
     #[serde(default)]
     pub trade_id: Option<String>,
     // added on
+    /// price in divine orbs
     #[serde(default)]
     #[gql(where, orderby)]
     pub divine_value: f64,
+    /// poe.ninja currency overview the currency was fetched from
     #[serde(default)]
-    #[gql(where)]
+    #[gql(where(filter = "CurrencyEndpoint"))]
     pub endpoint: CurrencyEndpoint,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject, GQLModel)]
 #[serde(rename_all = "camelCase")]
 pub struct Pay {
     pub id: i32,
@@ -60,17 +70,20 @@ pub struct Pay {
     pub get_currency_id: i32,
     #[serde(rename = "sample_time_utc")]
     pub sample_time_utc: String,
+    #[gql(where, orderby)]
     pub count: i32,
+    #[gql(where, orderby)]
     pub value: f64,
     #[serde(rename = "data_point_count")]
     pub data_point_count: i32,
     #[serde(rename = "includes_secondary")]
     pub includes_secondary: bool,
     #[serde(rename = "listing_count")]
+    #[gql(where, orderby)]
     pub listing_count: i32,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject, GQLModel)]
 #[serde(rename_all = "camelCase")]
 pub struct Receive {
     pub id: i32,
@@ -82,13 +95,16 @@ pub struct Receive {
     pub get_currency_id: i32,
     #[serde(rename = "sample_time_utc")]
     pub sample_time_utc: String,
+    #[gql(where, orderby)]
     pub count: i32,
+    #[gql(where, orderby)]
     pub value: f64,
     #[serde(rename = "data_point_count")]
     pub data_point_count: i32,
     #[serde(rename = "includes_secondary")]
     pub includes_secondary: bool,
     #[serde(rename = "listing_count")]
+    #[gql(where, orderby)]
     pub listing_count: i32,
 }
 
@@ -128,7 +144,7 @@ impl PartialEq for Currency {
 
 impl Eq for Currency {}
 
-#[derive(Default, Debug, Enum, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Enum, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, GQLFilter)]
 pub enum CurrencyEndpoint {
     #[default]
     Currency,
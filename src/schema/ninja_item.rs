@@ -1,9 +1,14 @@
 use async_graphql::{Enum, SimpleObject};
-use poe_api_derive::GQLModel;
+use poe_api_derive::{GQLFilter, GQLModel};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
+use std::sync::LazyLock;
 
-use super::{filters::FilterInput, ninja_common::Sparkline};
+use super::{
+    filters::FilterInput,
+    ninja_common::{Sparkline, SparklineOrderby, SparklineWhere},
+};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -13,8 +18,14 @@ pub struct ItemRaw {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize, SimpleObject, GQLModel)]
 #[serde(rename_all = "camelCase")]
+#[gql(orderby_key(
+    name = "chaos_per_listing",
+    key = "Item::chaos_per_listing",
+    desc = "chaos value divided by the number of listings"
+))]
 pub struct Item {
     pub id: i32,
+    /// name of the item, suffixed with (Relic) for relics
     #[gql(where, orderby)]
     pub name: String,
     pub icon: Option<String>,
@@ -22,6 +33,7 @@ pub struct Item {
     pub level_required: Option<i32>,
     #[gql(where, orderby)]
     pub base_type: Option<String>,
+    /// number of linked sockets
     #[gql(where, orderby)]
     pub links: Option<i32>,
     pub item_class: i32,
@@ -29,23 +41,30 @@ pub struct Item {
     pub gem_level: Option<i32>,
     #[gql(where)]
     pub gem_quality: Option<i32>,
+    /// price change over the last 7 days
+    #[gql(where, orderby, nested)]
     pub sparkline: Sparkline,
+    #[gql(where, orderby, nested)]
     pub low_confidence_sparkline: Sparkline,
-    #[gql(where)]
+    #[gql(where, orderby)]
     pub implicit_modifiers: Vec<Modifier>,
-    #[gql(where)]
+    #[gql(where, orderby)]
     pub explicit_modifiers: Vec<Modifier>,
     pub flavour_text: Option<String>,
     #[gql(where, orderby)]
     pub item_type: Option<String>,
+    /// price in chaos orbs
     #[gql(where, orderby)]
     pub chaos_value: f64,
     pub exalted_value: f64,
+    /// price in divine orbs
     #[gql(where, orderby)]
     pub divine_value: f64,
     pub count: i32,
     pub details_id: String,
     // pub trade_info: Vec<Value>,
+    /// number of listings currently on trade
+    #[gql(where, orderby)]
     pub listing_count: i32,
     #[gql(where, orderby)]
     pub variant: Option<String>,
@@ -53,8 +72,9 @@ pub struct Item {
     #[serde(default)]
     #[gql(where)]
     pub corrupted: bool,
+    /// poe.ninja item overview the item was fetched from
     #[serde(default)]
-    #[gql(where)]
+    #[gql(where(filter = "ItemEndpoint"))]
     pub endpoint: ItemEndpoint,
 }
 
@@ -73,14 +93,64 @@ impl PartialEq for Item {
 
 impl Eq for Item {}
 
+impl Item {
+    /// chaos value per listing, None when nothing is listed
+    pub fn chaos_per_listing(&self) -> Option<f64> {
+        (self.listing_count > 0).then(|| self.chaos_value / f64::from(self.listing_count))
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 #[serde(rename_all = "camelCase")]
 pub struct Modifier {
     pub text: String,
     pub optional: bool,
+    // added on
+    /// text with each numeric value and its sign replaced by #, e.g. "#% to Fire Resistance"
+    #[serde(default)]
+    pub template: String,
+    /// numeric values in order of appearance within the text
+    #[serde(default)]
+    pub values: Vec<ModifierValue>,
+}
+
+/// a roll range such as (15-30), fixed values have min == max
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct ModifierValue {
+    pub min: f64,
+    pub max: f64,
+}
+
+// matches either a (min-max) roll range or a plain number. a sign that does not follow
+// a word, unlike the dash of 10-20, belongs to the value, so templates are sign-free
+static MODIFIER_VALUE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:\B\+)?\((-?\d+(?:\.\d+)?)-(-?\d+(?:\.\d+)?)\)|((?:\B[+-])?\d+(?:\.\d+)?)")
+        .expect("invalid modifier value regex")
+});
+
+impl Modifier {
+    /// parses the text into its template and numeric values
+    pub fn parse_values(&mut self) {
+        let parse = |m: Option<regex::Match>| m.and_then(|m| m.as_str().parse::<f64>().ok());
+
+        self.values = MODIFIER_VALUE_RE
+            .captures_iter(&self.text)
+            .filter_map(
+                |caps| match (parse(caps.get(1)), parse(caps.get(2)), parse(caps.get(3))) {
+                    (Some(min), Some(max), _) => Some(ModifierValue { min, max }),
+                    (_, _, Some(value)) => Some(ModifierValue {
+                        min: value,
+                        max: value,
+                    }),
+                    _ => None,
+                },
+            )
+            .collect();
+        self.template = MODIFIER_VALUE_RE.replace_all(&self.text, "#").to_string();
+    }
 }
 
-#[derive(Default, Debug, Enum, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Enum, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, GQLFilter)]
 pub enum ItemEndpoint {
     // General
     Tattoo,
@@ -160,3 +230,65 @@ impl ToString for ItemEndpoint {
         .to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Modifier, ModifierValue};
+
+    fn parse(text: &str) -> Modifier {
+        let mut modifier = Modifier {
+            text: text.to_string(),
+            ..Modifier::default()
+        };
+        modifier.parse_values();
+        modifier
+    }
+
+    const fn value(min: f64, max: f64) -> ModifierValue {
+        ModifierValue { min, max }
+    }
+
+    #[test]
+    fn negative_values() {
+        let negative = parse("-10% to Fire Resistance");
+        assert_eq!(negative.values, [value(-10.0, -10.0)]);
+        assert_eq!(negative.template, "#% to Fire Resistance");
+
+        // both signs of a modifier share its template
+        let positive = parse("+10% to Fire Resistance");
+        assert_eq!(positive.values, [value(10.0, 10.0)]);
+        assert_eq!(positive.template, negative.template);
+
+        let modifier = parse("+25% to Cold Resistance, -0.5 to Maximum Charges");
+        assert_eq!(modifier.values, [value(25.0, 25.0), value(-0.5, -0.5)]);
+        assert_eq!(
+            modifier.template,
+            "#% to Cold Resistance, # to Maximum Charges"
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let modifier = parse("Adds (10-15) to (20-30) Physical Damage");
+        assert_eq!(modifier.values, [value(10.0, 15.0), value(20.0, 30.0)]);
+        assert_eq!(modifier.template, "Adds # to # Physical Damage");
+
+        let modifier = parse("(-20--10)% to Chaos Resistance");
+        assert_eq!(modifier.values, [value(-20.0, -10.0)]);
+
+        let modifier = parse("+(25-40) to maximum Life");
+        assert_eq!(modifier.values, [value(25.0, 40.0)]);
+        assert_eq!(modifier.template, "# to maximum Life");
+
+        // a dash between numbers separates them
+        let modifier = parse("Adds 10-20 Fire Damage");
+        assert_eq!(modifier.values, [value(10.0, 10.0), value(20.0, 20.0)]);
+    }
+
+    #[test]
+    fn no_values() {
+        let modifier = parse("Cannot be Frozen");
+        assert!(modifier.values.is_empty());
+        assert_eq!(modifier.template, "Cannot be Frozen");
+    }
+}
@@ -1,9 +1,103 @@
 use async_graphql::{Enum, InputType};
+use std::cmp::Ordering;
 
 #[derive(Enum, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Orderby {
+    /// ascending, nulls first
     Asc,
+    /// descending, nulls last
     Desc,
+    /// ascending, nulls first, the same as asc
+    AscNullsFirst,
+    /// ascending, nulls last
+    AscNullsLast,
+    /// descending, nulls first
+    DescNullsFirst,
+    /// descending, nulls last, the same as desc
+    DescNullsLast,
+}
+
+impl Orderby {
+    /// compares two field values according to the direction and null placement
+    pub fn compare<T: OrderbyKey + ?Sized>(self, a: &T, b: &T) -> Ordering {
+        let desc = matches!(
+            self,
+            Self::Desc | Self::DescNullsFirst | Self::DescNullsLast
+        );
+
+        match (a.key(), b.key()) {
+            (Some(a), Some(b)) => {
+                let ord = a.sort_cmp(b);
+                if desc {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            }
+            (a, b) => self.compare_nulls(a.is_none(), b.is_none()),
+        }
+    }
+
+    /// ordering of two values where at least one of them is null
+    pub const fn compare_nulls(self, a_is_null: bool, b_is_null: bool) -> Ordering {
+        let nulls_first = matches!(self, Self::Asc | Self::AscNullsFirst | Self::DescNullsFirst);
+
+        match (a_is_null, b_is_null) {
+            (true, false) if nulls_first => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (false, true) if nulls_first => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// total ordering used for sorting, so that e.g. NaN floats cannot panic
+pub trait SortKey {
+    fn sort_cmp(&self, other: &Self) -> Ordering;
+}
+
+impl SortKey for f64 {
+    fn sort_cmp(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
+
+macro_rules! impl_sort_key_ord {
+    ($($ty:ty),*) => {
+        $(
+            impl SortKey for $ty {
+                fn sort_cmp(&self, other: &Self) -> Ordering {
+                    self.cmp(other)
+                }
+            }
+        )*
+    };
+}
+
+impl_sort_key_ord!(i32, usize, bool, String);
+
+/// a field value that can be sorted on, returning None for nulls
+pub trait OrderbyKey {
+    type Key: SortKey + ?Sized;
+
+    fn key(&self) -> Option<&Self::Key>;
+}
+
+impl<T: SortKey> OrderbyKey for T {
+    type Key = T;
+
+    fn key(&self) -> Option<&Self::Key> {
+        Some(self)
+    }
+}
+
+impl<T: SortKey> OrderbyKey for Option<T> {
+    type Key = T;
+
+    fn key(&self) -> Option<&Self::Key> {
+        self.as_ref()
+    }
 }
 
 pub trait OrderbyInput
@@ -28,4 +122,127 @@ where
 
     /// cmp for purposes of orderby from graphql input
     fn cmp_orderby(&self, a: &Self::Output, b: &Self::Output) -> std::cmp::Ordering;
+
+    /// the direction of the (possibly nested) field being sorted on
+    fn direction(&self) -> Orderby;
+
+    /// cmp for optional nested values, nulls are placed by the nested direction
+    fn cmp_orderby_option(
+        &self,
+        a: Option<&Self::Output>,
+        b: Option<&Self::Output>,
+    ) -> std::cmp::Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => self.cmp_orderby(a, b),
+            (a, b) => self.direction().compare_nulls(a.is_none(), b.is_none()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Orderby, OrderbyInput};
+    use crate::schema::{
+        fixtures::{input, items, names},
+        ninja_item::{Item, ItemOrderby},
+    };
+
+    fn sorted(items: &mut Vec<Item>, value: serde_json::Value) {
+        ItemOrderby::orderby(items, input(value));
+    }
+
+    #[test]
+    fn sort_by_chaos_value() {
+        let mut items = items();
+        sorted(&mut items, json!([{"chaosValue": "DESC"}]));
+        assert_eq!(
+            names(&items[..3]),
+            ["Mageblood", "Headhunter", "Original Sin"]
+        );
+    }
+
+    #[test]
+    fn sort_by_multiple_fields() {
+        let mut items = items();
+        sorted(&mut items, json!([{"name": "ASC"}, {"chaosValue": "DESC"}]));
+
+        assert!(items.windows(2).all(|pair| {
+            pair[0].name < pair[1].name
+                || (pair[0].name == pair[1].name && pair[0].chaos_value >= pair[1].chaos_value)
+        }));
+    }
+
+    #[test]
+    fn sort_by_nested_field() {
+        let mut items = items();
+        sorted(&mut items, json!([{"sparkline": {"totalChange": "DESC"}}]));
+
+        assert!(items
+            .windows(2)
+            .all(|pair| pair[0].sparkline.total_change >= pair[1].sparkline.total_change));
+    }
+
+    #[test]
+    fn sort_by_modifier_count() {
+        let mut items = items();
+        sorted(&mut items, json!([{"explicitModifiers": "DESC"}]));
+
+        assert!(items
+            .windows(2)
+            .all(|pair| pair[0].explicit_modifiers.len() >= pair[1].explicit_modifiers.len()));
+    }
+
+    #[test]
+    fn sort_by_chaos_per_listing() {
+        let item = |id, chaos_value, listing_count| Item {
+            id,
+            chaos_value,
+            listing_count,
+            ..Default::default()
+        };
+        let mut items = vec![item(1, 100.0, 10), item(2, 50.0, 0), item(3, 30.0, 1)];
+        sorted(&mut items, json!([{"chaosPerListing": "DESC"}]));
+
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, [3, 1, 2]);
+    }
+
+    #[test]
+    fn nan_does_not_panic() {
+        let item = |id, divine_value| Item {
+            id,
+            divine_value,
+            ..Default::default()
+        };
+        let mut items = vec![item(1, 2.0), item(2, f64::NAN), item(3, 1.0)];
+        ItemOrderby::orderby(&mut items, vec![ItemOrderby::divine_value(Orderby::Asc)]);
+
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, [3, 1, 2]);
+    }
+
+    #[test]
+    fn null_ordering() {
+        let base_type = |id, base_type: Option<&str>| Item {
+            id,
+            base_type: base_type.map(String::from),
+            ..Default::default()
+        };
+        let ids = |order: Orderby| {
+            let mut items = vec![
+                base_type(1, Some("b")),
+                base_type(2, None),
+                base_type(3, Some("a")),
+            ];
+            ItemOrderby::orderby(&mut items, vec![ItemOrderby::base_type(order)]);
+            items.iter().map(|item| item.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(Orderby::Asc), [2, 3, 1]);
+        assert_eq!(ids(Orderby::Desc), [1, 3, 2]);
+        assert_eq!(ids(Orderby::AscNullsLast), [3, 1, 2]);
+        assert_eq!(ids(Orderby::DescNullsFirst), [2, 1, 3]);
+    }
 }
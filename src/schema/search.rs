@@ -0,0 +1,311 @@
+use async_graphql::{Enum, SimpleObject, Union};
+use futures::future;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, LazyLock, Mutex},
+};
+use tokio::sync::OnceCell;
+
+use super::cache::is_fresh;
+use super::currency::load_currencies;
+use super::item::load_items;
+use super::ninja_common::League;
+use super::ninja_currency::Currency;
+use super::ninja_item::Item;
+use super::upstream::{is_offline, FetchError};
+
+/// results scoring below this are not considered a match
+const MIN_SCORE: f64 = 0.3;
+
+type Trigram = [char; 3];
+
+#[derive(Debug, Enum, Clone, Copy, Eq, PartialEq, Serialize)]
+pub enum SearchKind {
+    Item,
+    Currency,
+}
+
+#[derive(Debug, Clone, Union, Serialize)]
+#[serde(untagged)]
+pub enum SearchHit {
+    Item(Item),
+    Currency(Currency),
+}
+
+#[derive(Debug, Clone, SimpleObject, Serialize)]
+pub struct SearchResult {
+    /// between 0 and 1, 1 being an exact match
+    pub score: f64,
+    pub kind: SearchKind,
+    /// the name, base type or variant that matched best
+    pub matched: String,
+    pub hit: SearchHit,
+}
+
+/// lowercased trigrams of each word, padded like `pg_trgm` so that word starts weigh more
+fn trigrams(text: &str) -> HashSet<Trigram> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .flat_map(|word| {
+            let chars: Vec<_> = "  ".chars().chain(word.chars()).chain([' ']).collect();
+            chars
+                .windows(3)
+                .map(|w| [w[0], w[1], w[2]])
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Doc {
+    Item(usize),
+    Currency(usize),
+}
+
+impl Doc {
+    const fn kind(self) -> SearchKind {
+        match self {
+            Self::Item(_) => SearchKind::Item,
+            Self::Currency(_) => SearchKind::Currency,
+        }
+    }
+}
+
+/// a single searchable field of an item or currency
+#[derive(Debug)]
+struct Entry {
+    doc: Doc,
+    text: String,
+    trigram_count: usize,
+}
+
+/// trigram index over item and currency names
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    items: Arc<Vec<Item>>,
+    currencies: Arc<Vec<Currency>>,
+    entries: Vec<Entry>,
+    postings: HashMap<Trigram, Vec<usize>>,
+}
+
+impl SearchIndex {
+    pub fn new(items: Arc<Vec<Item>>, currencies: Arc<Vec<Currency>>) -> Self {
+        let mut index = Self::default();
+
+        for (i, item) in items.iter().enumerate() {
+            let fields = [
+                Some(&item.name),
+                item.base_type.as_ref(),
+                item.variant.as_ref(),
+            ];
+            for text in fields.into_iter().flatten() {
+                index.insert(Doc::Item(i), text);
+            }
+        }
+        for (i, currency) in currencies.iter().enumerate() {
+            index.insert(Doc::Currency(i), &currency.name);
+        }
+
+        index.items = items;
+        index.currencies = currencies;
+        index
+    }
+
+    fn insert(&mut self, doc: Doc, text: &str) {
+        let trigrams = trigrams(text);
+        if trigrams.is_empty() {
+            return;
+        }
+
+        let entry = self.entries.len();
+        for trigram in &trigrams {
+            self.postings.entry(*trigram).or_default().push(entry);
+        }
+        self.entries.push(Entry {
+            doc,
+            text: text.to_string(),
+            trigram_count: trigrams.len(),
+        });
+    }
+
+    /// best matches first, each item or currency appears at most once
+    pub fn search(&self, query: &str, limit: usize, kinds: &[SearchKind]) -> Vec<SearchResult> {
+        let query = trigrams(query);
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for trigram in &query {
+            for entry in self.postings.get(trigram).into_iter().flatten() {
+                *shared.entry(*entry).or_default() += 1;
+            }
+        }
+
+        // keep the best scoring field of each doc
+        let mut best: HashMap<Doc, (f64, &Entry)> = HashMap::new();
+        for (entry, shared) in shared {
+            let entry = &self.entries[entry];
+            if !kinds.contains(&entry.doc.kind()) {
+                continue;
+            }
+
+            let score = score(shared, query.len(), entry.trigram_count);
+            if score < MIN_SCORE {
+                continue;
+            }
+            best.entry(entry.doc)
+                .and_modify(|current| {
+                    if score > current.0 {
+                        *current = (score, entry);
+                    }
+                })
+                .or_insert((score, entry));
+        }
+
+        let mut results: Vec<_> = best.into_iter().collect();
+        results.sort_by(|(_, (a_score, a)), (_, (b_score, b))| {
+            b_score.total_cmp(a_score).then_with(|| a.text.cmp(&b.text))
+        });
+        results
+            .into_iter()
+            .take(limit)
+            .map(|(doc, (score, entry))| SearchResult {
+                score,
+                kind: doc.kind(),
+                matched: entry.text.clone(),
+                hit: match doc {
+                    Doc::Item(i) => SearchHit::Item(self.items[i].clone()),
+                    Doc::Currency(i) => SearchHit::Currency(self.currencies[i].clone()),
+                },
+            })
+            .collect()
+    }
+}
+
+/// average of the jaccard similarity and how much of the query is covered
+#[allow(clippy::cast_precision_loss)]
+fn score(shared: usize, query_count: usize, text_count: usize) -> f64 {
+    let similarity = shared as f64 / (query_count + text_count - shared) as f64;
+    let coverage = shared as f64 / query_count as f64;
+    f64::midpoint(similarity, coverage)
+}
+
+/// built once per league from the data in memory, and dropped when new data is
+/// remembered, so concurrent searches share a single build
+type IndexCell = Arc<OnceCell<Arc<SearchIndex>>>;
+
+static INDEXES: LazyLock<Mutex<HashMap<League, IndexCell>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+async fn league_index(league: League) -> Result<Arc<SearchIndex>, FetchError> {
+    // stale data is fetched again first, which drops the index built from it.
+    // recordings never change, so offline there is nothing to refresh
+    let fresh = is_offline() || (is_fresh("item", league) && is_fresh("currency", league));
+    if !fresh {
+        future::try_join(load_items(league), load_currencies(league)).await?;
+    }
+
+    let cell = Arc::clone(
+        INDEXES
+            .lock()
+            .expect("search index lock poisoned")
+            .entry(league)
+            .or_default(),
+    );
+    let index = cell
+        .get_or_try_init(|| async {
+            let (items, currencies) =
+                future::try_join(load_items(league), load_currencies(league)).await?;
+            Ok::<_, FetchError>(Arc::new(SearchIndex::new(items, currencies)))
+        })
+        .await?;
+    Ok(Arc::clone(index))
+}
+
+/// the index is rebuilt from the cache on the next search
+pub fn invalidate_index(league: League) {
+    INDEXES
+        .lock()
+        .expect("search index lock poisoned")
+        .remove(&league);
+}
+
+pub async fn search(
+    query: &str,
+    league: League,
+    limit: usize,
+    kinds: &[SearchKind],
+) -> Result<Vec<SearchResult>, FetchError> {
+    Ok(league_index(league).await?.search(query, limit, kinds))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{SearchHit, SearchIndex, SearchKind, SearchResult};
+    use crate::schema::{fixtures::items, ninja_currency::Currency};
+
+    const ALL: [SearchKind; 2] = [SearchKind::Item, SearchKind::Currency];
+
+    fn index() -> SearchIndex {
+        let currency = |name: &str| Currency {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        SearchIndex::new(
+            Arc::new(items()),
+            Arc::new(vec![currency("Divine Orb"), currency("Mirror of Kalandra")]),
+        )
+    }
+
+    fn matched(results: &[SearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|result| result.matched.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn misspelled_name() {
+        let results = index().search("hedhunter", 2, &ALL);
+        assert_eq!(matched(&results), ["Headhunter", "Replica Headhunter"]);
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn exact_name_scores_one() {
+        let results = index().search("mageblood", 10, &ALL);
+        assert_eq!(matched(&results[..1]), ["Mageblood"]);
+        assert!((results[0].score - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn matches_base_type() {
+        let results = index().search("leather belt", 1, &[SearchKind::Item]);
+        assert!(matches!(
+            &results[0].hit,
+            SearchHit::Item(item) if item.base_type.as_deref() == Some("Leather Belt")
+        ));
+    }
+
+    #[test]
+    fn filters_by_kind() {
+        let index = index();
+        let results = index.search("divine", 10, &[SearchKind::Currency]);
+        assert_eq!(matched(&results), ["Divine Orb"]);
+
+        assert!(index
+            .search("divine orb", 10, &[SearchKind::Item])
+            .is_empty());
+    }
+
+    #[test]
+    fn unrelated_query() {
+        assert!(index().search("zzzz", 10, &ALL).is_empty());
+        assert!(index().search("  ", 10, &ALL).is_empty());
+    }
+}
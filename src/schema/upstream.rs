@@ -0,0 +1,459 @@
+use async_graphql::EnumType;
+use futures::future;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, RwLock},
+    time::Instant,
+};
+use tracing::{field, info, warn, Span};
+
+use super::cache::{cache_path, timestamp, write_atomically, CACHE_THRESHOLD};
+use super::client::{client, Validators};
+use super::metrics::record_upstream;
+use super::ninja_common::League;
+use super::ninja_currency::CurrencyEndpoint;
+use super::ninja_item::ItemEndpoint;
+
+pub const POE_NINJA_URL: &str = "https://poe.ninja";
+
+/// where poe.ninja responses come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    /// poe.ninja, or a stand-in serving the same api at `base_url`
+    Live { base_url: String },
+    /// responses recorded by [`record`], read from `{data_dir}/{league}/{overview}/{endpoint}.json`
+    Offline(PathBuf),
+}
+
+impl Default for Upstream {
+    fn default() -> Self {
+        Self::Live {
+            base_url: POE_NINJA_URL.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Live { base_url } => f.write_str(base_url),
+            Self::Offline(data_dir) => write!(f, "offline:{}", data_dir.display()),
+        }
+    }
+}
+
+static UPSTREAM: LazyLock<RwLock<Upstream>> = LazyLock::new(RwLock::default);
+
+/// a failed upstream request, recording or cache access
+#[derive(Debug)]
+pub enum FetchError {
+    Request {
+        endpoint: String,
+        source: reqwest::Error,
+    },
+    Parse {
+        endpoint: String,
+        source: serde_json::Error,
+    },
+    Recording {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Cache(std::io::Error),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request { endpoint, source } => {
+                write!(f, "could not fetch data from endpoint {endpoint}: {source}")
+            }
+            Self::Parse { endpoint, source } => {
+                write!(f, "could not parse data from endpoint {endpoint}: {source}")
+            }
+            Self::Recording { path, source } => {
+                write!(f, "could not read {}: {source}", path.display())
+            }
+            Self::Cache(source) => write!(f, "could not access the cache: {source}"),
+        }
+    }
+}
+
+impl Error for FetchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Request { source, .. } => Some(source),
+            Self::Parse { source, .. } => Some(source),
+            Self::Recording { source, .. } | Self::Cache(source) => Some(source),
+        }
+    }
+}
+
+impl From<std::io::Error> for FetchError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Cache(err)
+    }
+}
+
+/// unix timestamps in seconds of the last answered and the last failed poe.ninja request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpstreamHealth {
+    pub last_success: Option<u64>,
+    pub last_failure: Option<u64>,
+}
+
+impl UpstreamHealth {
+    /// answered within the cache threshold or since the last failed request, so a single
+    /// failing endpoint does not count. `None` if poe.ninja was never contacted
+    pub fn reachable(&self, now: u64) -> Option<bool> {
+        match (self.last_success, self.last_failure) {
+            (None, None) => None,
+            (None, Some(_)) => Some(false),
+            (Some(success), failure) => {
+                Some(failure <= Some(success) || now.saturating_sub(success) < CACHE_THRESHOLD)
+            }
+        }
+    }
+}
+
+static HEALTH: Mutex<UpstreamHealth> = Mutex::new(UpstreamHealth {
+    last_success: None,
+    last_failure: None,
+});
+
+pub fn upstream_health() -> UpstreamHealth {
+    *HEALTH.lock().expect("upstream health lock poisoned")
+}
+
+fn note_request(answered: bool) {
+    let mut health = HEALTH.lock().expect("upstream health lock poisoned");
+    if answered {
+        health.last_success = Some(timestamp());
+    } else {
+        health.last_failure = Some(timestamp());
+    }
+}
+
+pub fn set_upstream(upstream: Upstream) {
+    *UPSTREAM.write().expect("upstream lock poisoned") = upstream;
+}
+
+pub fn upstream() -> Upstream {
+    UPSTREAM.read().expect("upstream lock poisoned").clone()
+}
+
+/// offline responses are already on disk, so they are not cached again
+pub fn is_offline() -> bool {
+    matches!(upstream(), Upstream::Offline(_))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overview {
+    Item,
+    Currency,
+}
+
+impl Overview {
+    const fn api(self) -> &'static str {
+        match self {
+            Self::Item => "itemoverview",
+            Self::Currency => "currencyoverview",
+        }
+    }
+
+    const fn dir(self) -> &'static str {
+        match self {
+            Self::Item => "item",
+            Self::Currency => "currency",
+        }
+    }
+}
+
+fn recording_path(data_dir: &Path, overview: Overview, league: League, endpoint: &str) -> PathBuf {
+    data_dir
+        .join(league.to_string())
+        .join(overview.dir())
+        .join(format!("{endpoint}.json"))
+}
+
+fn overview_url(base_url: &str, overview: Overview, league: League, endpoint: &str) -> String {
+    format!(
+        "{base_url}/api/data/{}?league={}&type={}",
+        overview.api(),
+        league.to_string(),
+        endpoint
+    )
+}
+
+/// raw response body of a poe.ninja overview
+async fn fetch_text(
+    base_url: &str,
+    overview: Overview,
+    league: League,
+    endpoint: &str,
+) -> reqwest::Result<String> {
+    client()
+        .get_text(&overview_url(base_url, overview, league, endpoint))
+        .await
+}
+
+/// last response of an endpoint, kept to revalidate it instead of downloading it again
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredResponse {
+    validators: Validators,
+    body: String,
+}
+
+fn stored_response_path(overview: Overview, league: League, endpoint: &str) -> PathBuf {
+    cache_path(&format!(
+        "__poe__{}__{}__{endpoint}.json",
+        overview.dir(),
+        league.to_string()
+    ))
+}
+
+/// removes the stored responses of the endpoints, so that they are downloaded in full
+/// instead of revalidated. returns whether any was stored
+pub fn forget_responses(
+    overview: Overview,
+    league: League,
+    endpoints: &[String],
+) -> std::io::Result<bool> {
+    let mut removed = false;
+    for endpoint in endpoints {
+        match std::fs::remove_file(stored_response_path(overview, league, endpoint)) {
+            Ok(()) => removed = true,
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(removed)
+}
+
+/// like [`fetch_text`], but only downloads the overview if it changed since the last
+/// response. a missing or unreadable stored response means a full download
+#[tracing::instrument(
+    name = "upstream",
+    skip(base_url),
+    fields(status = field::Empty, bytes = field::Empty, duration_ms = field::Empty)
+)]
+async fn fetch_text_if_modified(
+    base_url: &str,
+    overview: Overview,
+    league: League,
+    endpoint: &str,
+) -> Result<String, FetchError> {
+    let path = stored_response_path(overview, league, endpoint);
+    let mut stored = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<StoredResponse>(&text).ok());
+    let mut validators = stored
+        .as_ref()
+        .map(|stored| stored.validators.clone())
+        .unwrap_or_default();
+
+    let url = overview_url(base_url, overview, league, endpoint);
+    let (body, validators) = loop {
+        match request_if_modified(&url, overview, endpoint, &validators).await? {
+            Some(fetched) => break fetched,
+            None => match stored.take() {
+                Some(stored) => return Ok(stored.body),
+                // nothing to serve for not modified, so ask again without validators,
+                // which is always answered with a body
+                None => validators = Validators::default(),
+            },
+        }
+    };
+
+    if !validators.is_empty() {
+        let stored = StoredResponse { validators, body };
+        let text = serde_json::to_string(&stored).map_err(std::io::Error::from)?;
+        write_atomically(&path, text)?;
+        return Ok(stored.body);
+    }
+    Ok(body)
+}
+
+/// one conditional request, recorded in the metrics and the span of the fetch
+async fn request_if_modified(
+    url: &str,
+    overview: Overview,
+    endpoint: &str,
+    validators: &Validators,
+) -> Result<Option<(String, Validators)>, FetchError> {
+    let start = Instant::now();
+    let fetched = client().get_text_if_modified(url, validators).await;
+
+    let status = match &fetched {
+        Ok(Some(_)) => "200".to_string(),
+        Ok(None) => "304".to_string(),
+        Err(err) => err
+            .status()
+            .map_or_else(|| "error".to_string(), |status| status.as_u16().to_string()),
+    };
+    let elapsed = start.elapsed();
+    record_upstream(overview.dir(), endpoint, &status, elapsed);
+    // error statuses are answers too, but count as failures as nothing can be served
+    note_request(fetched.is_ok());
+
+    let span = Span::current();
+    span.record("status", status.as_str()).record(
+        "duration_ms",
+        u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+    );
+    match &fetched {
+        Ok(fetched) => {
+            let bytes = fetched.as_ref().map_or(0, |(body, _)| body.len());
+            span.record("bytes", bytes);
+            info!("poe.ninja answered");
+        }
+        Err(err) => warn!(error = %err, "poe.ninja request failed"),
+    }
+
+    fetched.map_err(|source| FetchError::Request {
+        endpoint: endpoint.to_string(),
+        source,
+    })
+}
+
+/// fetches an overview endpoint, or reads its recording when offline.
+/// endpoints that were not recorded are empty
+pub async fn fetch_overview<T: DeserializeOwned + Default>(
+    overview: Overview,
+    league: League,
+    endpoint: &str,
+) -> Result<T, FetchError> {
+    let text = match upstream() {
+        Upstream::Live { base_url } => {
+            fetch_text_if_modified(&base_url, overview, league, endpoint).await?
+        }
+        Upstream::Offline(data_dir) => {
+            let path = recording_path(&data_dir, overview, league, endpoint);
+            match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) if err.kind() == ErrorKind::NotFound => return Ok(T::default()),
+                Err(source) => return Err(FetchError::Recording { path, source }),
+            }
+        }
+    };
+
+    serde_json::from_str(&text).map_err(|source| FetchError::Parse {
+        endpoint: endpoint.to_string(),
+        source,
+    })
+}
+
+/// records the live responses of every endpoint of the league into `data_dir`
+pub async fn record(data_dir: &Path, league: League) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let base_url = match upstream() {
+        Upstream::Live { base_url } => base_url,
+        Upstream::Offline(_) => return Err("cannot record while offline".into()),
+    };
+
+    let endpoints = ItemEndpoint::items()
+        .iter()
+        .map(|item| (Overview::Item, item.value.to_string()))
+        .chain(
+            CurrencyEndpoint::items()
+                .iter()
+                .map(|item| (Overview::Currency, item.value.to_string())),
+        );
+
+    let base_url = &base_url;
+    let responses = future::join_all(endpoints.map(|(overview, endpoint)| async move {
+        let text = fetch_text(base_url, overview, league, &endpoint).await;
+        (recording_path(data_dir, overview, league, &endpoint), text)
+    }))
+    .await;
+
+    let mut paths = Vec::new();
+    for (path, text) in responses {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, text?)?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{fetch_overview, recording_path, set_upstream, Overview, Upstream, UpstreamHealth};
+    use crate::schema::{
+        cache::CACHE_THRESHOLD,
+        item::get_items,
+        ninja_common::League,
+        ninja_item::{ItemOrderby, ItemRaw},
+        orderby::Orderby,
+    };
+
+    fn write(data_dir: &Path, overview: Overview, endpoint: &str, contents: &str) {
+        let path = recording_path(data_dir, overview, League::Standard, endpoint);
+        std::fs::create_dir_all(path.parent().expect("parent dir")).expect("create dir");
+        std::fs::write(path, contents).expect("write recording");
+    }
+
+    #[tokio::test]
+    async fn reads_recordings_when_offline() {
+        let data_dir = std::env::temp_dir().join(format!("poe-api-offline-{}", std::process::id()));
+        write(
+            &data_dir,
+            Overview::Item,
+            "UniqueAccessory",
+            include_str!("jewelry.json"),
+        );
+        set_upstream(Upstream::Offline(data_dir.clone()));
+
+        let recorded: ItemRaw = fetch_overview(Overview::Item, League::Standard, "UniqueAccessory")
+            .await
+            .expect("recorded endpoint");
+        assert!(!recorded.lines.is_empty());
+
+        let missing: ItemRaw = fetch_overview(Overview::Item, League::Standard, "Scarab")
+            .await
+            .expect("missing endpoint is empty");
+        assert!(missing.lines.is_empty());
+
+        let items = Box::pin(get_items(
+            None,
+            vec![ItemOrderby::chaos_value(Orderby::Desc)],
+            Some(League::Standard),
+        ))
+        .await
+        .expect("offline items");
+        assert_eq!(items.len(), recorded.lines.len());
+        assert_eq!(items[0].name, "Mageblood");
+
+        set_upstream(Upstream::default());
+        std::fs::remove_dir_all(data_dir).expect("remove data dir");
+    }
+
+    #[test]
+    fn reachable_if_answered_recently() {
+        let now = 10 * CACHE_THRESHOLD;
+        let health = |last_success, last_failure| {
+            UpstreamHealth {
+                last_success,
+                last_failure,
+            }
+            .reachable(now)
+        };
+        assert_eq!(health(None, None), None);
+        assert_eq!(health(None, Some(now)), Some(false));
+        assert_eq!(health(Some(now - 1), Some(now)), Some(true));
+        // nothing was requested since
+        assert_eq!(health(Some(now - 2 * CACHE_THRESHOLD), None), Some(true));
+        assert_eq!(
+            health(Some(now - 2 * CACHE_THRESHOLD), Some(now - 1)),
+            Some(false)
+        );
+    }
+}
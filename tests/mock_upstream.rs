@@ -0,0 +1,659 @@
+//! runs graphql queries against a local stand-in for poe.ninja.
+//! the league picks the behaviour of the stand-in: Standard serves the fixtures,
+//! Hardcore fails with 500, Ruthless answers garbage, Hardcore+Ruthless is too slow and
+//! the previous league is rate limited once per endpoint before serving the fixtures.
+//! its hardcore variant tags responses with an `ETag` and answers 304 when they are sent back,
+//! its ruthless variant serves the fixtures to the admin tests and its hardcore ruthless
+//! variant serves them until an outage is started, then fails with 500.
+//! the current hardcore league serves the fixtures and counts the requests
+
+use async_graphql::{Request, Response, Variables};
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap, StatusCode},
+    routing::get,
+    Router,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+use poe_api::access::{parse_api_key, AccessConfig};
+use poe_api::schema::{
+    build_schema, set_cache_dir, set_client_config, set_upstream, BearerToken, ClientConfig,
+    League, Upstream,
+};
+use poe_api::AppConfig;
+
+const ITEMS: &str = include_str!("../src/schema/jewelry.json");
+const CURRENCIES: &str = include_str!("../src/schema/currencies.json");
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+const ADMIN_TOKEN: &str = "secret";
+
+type Params = Query<HashMap<String, String>>;
+type Reply = (StatusCode, HeaderMap, String);
+
+const ETAG: &str = "\"v1\"";
+
+// requests per endpoint of the rate limited league
+static ATTEMPTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// endpoints answered with 304 Not Modified
+static NOT_MODIFIED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// requests per endpoint of the current hardcore league
+static REQUESTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// whether the previous hardcore ruthless league fails
+static OUTAGE: AtomicBool = AtomicBool::new(false);
+
+async fn respond(
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    fixture: &str,
+    empty: &str,
+) -> Reply {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !user_agent.starts_with("poe-api/") {
+        return (StatusCode::FORBIDDEN, HeaderMap::new(), String::new());
+    }
+
+    let league = params.get("league").map_or("", String::as_str);
+    let (status, body) = match league {
+        "Standard" | "Ruthless Crucible" => (StatusCode::OK, fixture.to_string()),
+        "Hardcore" => (StatusCode::INTERNAL_SERVER_ERROR, "oops".to_string()),
+        "Ruthless" => (StatusCode::OK, r#"{"lines": [{"name": 1}"#.to_string()),
+        "Hardcore Ruthless" => {
+            tokio::time::sleep(TIMEOUT * 10).await;
+            (StatusCode::OK, empty.to_string())
+        }
+        "Crucible" => {
+            let endpoint = params.get("type").cloned().unwrap_or_default();
+            let mut attempts = ATTEMPTS.lock().expect("attempts lock");
+            if attempts.contains(&endpoint) {
+                (StatusCode::OK, fixture.to_string())
+            } else {
+                attempts.push(endpoint);
+                let mut headers = HeaderMap::new();
+                headers.insert(header::RETRY_AFTER, "0".parse().expect("header value"));
+                return (StatusCode::TOO_MANY_REQUESTS, headers, String::new());
+            }
+        }
+        "Hardcore Ancestor" => {
+            let endpoint = params.get("type").cloned().unwrap_or_default();
+            REQUESTS.lock().expect("requests lock").push(endpoint);
+            (StatusCode::OK, fixture.to_string())
+        }
+        "HC Ruthless Crucible" if OUTAGE.load(Ordering::SeqCst) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, "oops".to_string())
+        }
+        "HC Ruthless Crucible" => (StatusCode::OK, fixture.to_string()),
+        "Hardcore Crucible" => {
+            if headers
+                .get(header::IF_NONE_MATCH)
+                .is_some_and(|etag| etag == ETAG)
+            {
+                let endpoint = params.get("type").cloned().unwrap_or_default();
+                NOT_MODIFIED
+                    .lock()
+                    .expect("not modified lock")
+                    .push(endpoint);
+                return (StatusCode::NOT_MODIFIED, HeaderMap::new(), String::new());
+            }
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ETAG, ETAG.parse().expect("header value"));
+            return (StatusCode::OK, headers, fixture.to_string());
+        }
+        _ => (StatusCode::NOT_FOUND, String::new()),
+    };
+    (status, HeaderMap::new(), body)
+}
+
+async fn item_overview(headers: HeaderMap, Query(params): Params) -> Reply {
+    let empty = r#"{"lines": []}"#;
+    let fixture = if params.get("type").map(String::as_str) == Some("UniqueAccessory") {
+        ITEMS
+    } else {
+        empty
+    };
+    respond(&headers, &params, fixture, empty).await
+}
+
+async fn currency_overview(headers: HeaderMap, Query(params): Params) -> Reply {
+    let empty = r#"{"lines": [], "currencyDetails": []}"#;
+    let fixture = if params.get("type").map(String::as_str) == Some("Currency") {
+        CURRENCIES
+    } else {
+        empty
+    };
+    respond(&headers, &params, fixture, empty).await
+}
+
+fn cache_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("poe-api-mock-{}", std::process::id()))
+}
+
+/// starts the stand-in on its own runtime, so it outlives the runtime of each test
+fn mock_upstream() {
+    static BASE_URL: OnceLock<String> = OnceLock::new();
+
+    BASE_URL.get_or_init(|| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind mock upstream");
+        listener
+            .set_nonblocking(true)
+            .expect("nonblocking listener");
+        let base_url = format!("http://{}", listener.local_addr().expect("local addr"));
+
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("mock upstream runtime");
+            runtime.block_on(async {
+                let app = Router::new()
+                    .route("/api/data/itemoverview", get(item_overview))
+                    .route("/api/data/currencyoverview", get(currency_overview));
+                let listener = tokio::net::TcpListener::from_std(listener).expect("tokio listener");
+                axum::serve(listener, app)
+                    .await
+                    .expect("serve mock upstream");
+            });
+        });
+
+        std::fs::create_dir_all(cache_dir()).expect("create cache dir");
+        set_cache_dir(cache_dir());
+        set_upstream(Upstream::Live {
+            base_url: base_url.clone(),
+        });
+        set_client_config(ClientConfig {
+            timeout: TIMEOUT,
+            retries: 1,
+            backoff: Duration::from_millis(10),
+            concurrency: 64,
+            ..ClientConfig::default()
+        })
+        .expect("client config");
+
+        base_url
+    });
+}
+
+async fn query(request: impl Into<Request>) -> Response {
+    mock_upstream();
+    build_schema(Some(ADMIN_TOKEN.to_string()))
+        .execute(request)
+        .await
+}
+
+async fn as_admin(query_str: &str, token: &str) -> Response {
+    query(Request::new(query_str).data(BearerToken(token.to_string()))).await
+}
+
+async fn data(query_str: &str) -> Value {
+    let response = query(query_str).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().expect("json data")
+}
+
+async fn error(query_str: &str) -> String {
+    let response = query(query_str).await;
+    assert_eq!(response.errors.len(), 1, "{:?}", response.errors);
+    response.errors[0].message.clone()
+}
+
+/// serves the whole app on a free port
+async fn serve_app(config: AppConfig) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind app");
+    let base_url = format!("http://{}", listener.local_addr().expect("local addr"));
+    let app = poe_api::app(config)
+        .expect("app")
+        .into_make_service_with_connect_info::<std::net::SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    base_url
+}
+
+#[tokio::test]
+async fn items() {
+    let data = data(
+        r#"{ item(league: STANDARD, orderby: [{ chaosValue: DESC }]) { name detailsId chaosValue } }"#,
+    )
+    .await;
+    let items = data["item"].as_array().expect("items");
+
+    assert_eq!(items.len(), 247);
+    assert_eq!(
+        items[0],
+        json!({ "name": "Mageblood", "detailsId": "mageblood-heavy-belt", "chaosValue": 81484.52 })
+    );
+}
+
+#[tokio::test]
+async fn relics_are_renamed() {
+    let data = data(
+        r#"{ item(league: STANDARD, where: { name: { icontains: "ashes of the stars" } }) { name detailsId } }"#,
+    )
+    .await;
+
+    assert_eq!(
+        data["item"],
+        json!([
+            { "name": "Ashes of the Stars", "detailsId": "ashes-of-the-stars-onyx-amulet" },
+            { "name": "Ashes of the Stars (Relic)", "detailsId": "ashes-of-the-stars-onyx-amulet-relic" },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn divine_value_is_derived() {
+    let divine = data(
+        r#"{ currency(league: STANDARD, where: { name: { eq: "Divine Orb" } }) { name chaosValue divineValue } }"#,
+    )
+    .await;
+
+    assert_eq!(
+        divine["currency"],
+        json!([{ "name": "Divine Orb", "chaosValue": 233.48, "divineValue": 1.0 }])
+    );
+
+    let all = data(
+        r#"{ currency(league: STANDARD, orderby: [{ chaosValue: DESC }]) { chaosValue divineValue } }"#,
+    )
+    .await;
+    for currency in all["currency"].as_array().expect("currencies") {
+        let chaos = currency["chaosValue"].as_f64().expect("chaos value");
+        let divine = currency["divineValue"].as_f64().expect("divine value");
+        assert!((divine - chaos / 233.48).abs() < 1e-9);
+    }
+}
+
+#[tokio::test]
+async fn search() {
+    let data =
+        data(r#"{ search(query: "hedhunter", league: STANDARD, limit: 1) { kind matched } }"#)
+            .await;
+
+    assert_eq!(
+        data["search"],
+        json!([{ "kind": "ITEM", "matched": "Headhunter" }])
+    );
+}
+
+#[tokio::test]
+async fn retries_rate_limited_requests() {
+    let data = data(
+        r#"{ currency(league: PREV_STANDARD, where: { name: { eq: "Divine Orb" } }) { chaosValue } }"#,
+    )
+    .await;
+    assert_eq!(data["currency"], json!([{ "chaosValue": 233.48 }]));
+
+    // each endpoint was rate limited once
+    let mut attempts = ATTEMPTS.lock().expect("attempts lock").clone();
+    attempts.sort();
+    assert_eq!(attempts, ["Currency", "Fragment"]);
+}
+
+#[tokio::test]
+async fn concurrent_requests_share_a_fetch() {
+    let query = r"{ currency(league: TMP_HARDCORE, orderby: [{ chaosValue: DESC }]) { name } }";
+    let responses = futures::future::join_all((0..8).map(|_| data(query))).await;
+    assert!(responses.iter().all(|response| *response == responses[0]));
+
+    let mut requests = REQUESTS.lock().expect("requests lock").clone();
+    requests.sort();
+    assert_eq!(requests, ["Currency", "Fragment"]);
+}
+
+#[tokio::test]
+async fn revalidates_until_invalidated() {
+    let query =
+        r"{ currency(league: PREV_HARDCORE, orderby: [{ chaosValue: DESC }]) { name chaosValue } }";
+    let not_modified = || {
+        let mut not_modified = NOT_MODIFIED.lock().expect("not modified lock").clone();
+        not_modified.sort();
+        not_modified
+    };
+    let fetched = data(query).await;
+    assert!(not_modified().is_empty());
+
+    let response = as_admin(
+        r"mutation { refreshNow(league: PREV_HARDCORE, kind: CURRENCY) { rows } }",
+        ADMIN_TOKEN,
+    )
+    .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(data(query).await, fetched);
+    assert_eq!(not_modified(), ["Currency", "Fragment"]);
+
+    // invalidating drops the etags too, so nothing is revalidated
+    let response = as_admin(
+        r"mutation { invalidateCache(league: PREV_HARDCORE) }",
+        ADMIN_TOKEN,
+    )
+    .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(data(query).await, fetched);
+    assert_eq!(not_modified(), ["Currency", "Fragment"]);
+}
+
+#[tokio::test]
+async fn cache_status() {
+    data(r"{ item(league: STANDARD) { name } }").await;
+
+    let status = data(
+        r"{ cacheStatus(league: STANDARD, kind: ITEM) { league kind rows fresh source size } }",
+    )
+    .await;
+    let entry = &status["cacheStatus"][0];
+    assert_eq!(entry["league"], "STANDARD");
+    assert_eq!(entry["rows"], 247);
+    assert_eq!(entry["fresh"], true);
+    assert!(entry["source"]
+        .as_str()
+        .is_some_and(|source| source.starts_with("http://127.0.0.1:")));
+    assert!(entry["size"].as_u64().is_some_and(|size| size > 0));
+}
+
+#[tokio::test]
+async fn admin_mutations_need_the_token() {
+    let mutation = r"mutation { invalidateCache(league: PREV_RUTHLESS) }";
+
+    let response = query(mutation).await;
+    assert_eq!(response.errors[0].message, "invalid admin token");
+    let response = as_admin(mutation, "wrong").await;
+    assert_eq!(response.errors[0].message, "invalid admin token");
+
+    let response = build_schema(None)
+        .execute(Request::new(mutation).data(BearerToken(ADMIN_TOKEN.to_string())))
+        .await;
+    assert!(response.errors[0]
+        .message
+        .starts_with("admin mutations are disabled"));
+}
+
+#[tokio::test]
+async fn invalidate_and_refresh() {
+    let status = r"{ cacheStatus(league: PREV_RUTHLESS) { kind rows } }";
+
+    let response = as_admin(
+        r"mutation { refreshNow(league: PREV_RUTHLESS) { kind rows } }",
+        ADMIN_TOKEN,
+    )
+    .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let refreshed = response.data.into_json().expect("json data");
+    assert_eq!(
+        refreshed["refreshNow"],
+        json!([{ "kind": "ITEM", "rows": 247 }, { "kind": "CURRENCY", "rows": 109 }])
+    );
+    assert_eq!(data(status).await["cacheStatus"], refreshed["refreshNow"]);
+
+    let response = as_admin(
+        r"mutation { invalidateCache(league: PREV_RUTHLESS, kind: ITEM) }",
+        ADMIN_TOKEN,
+    )
+    .await;
+    assert_eq!(
+        response.data.into_json().expect("json data"),
+        json!({ "invalidateCache": 1 })
+    );
+    assert_eq!(
+        data(status).await["cacheStatus"],
+        json!([{ "kind": "CURRENCY", "rows": 109 }])
+    );
+}
+
+#[tokio::test]
+async fn failed_refresh_keeps_the_cache() {
+    let query = r"{ currency(league: PREV_HARDCORE_RUTHLESS, orderby: [{ chaosValue: DESC }]) { name chaosValue } }";
+    let fetched = data(query).await;
+
+    OUTAGE.store(true, Ordering::SeqCst);
+    let response = as_admin(
+        r"mutation { refreshNow(league: PREV_HARDCORE_RUTHLESS, kind: CURRENCY) { rows } }",
+        ADMIN_TOKEN,
+    )
+    .await;
+    assert!(
+        response.errors[0].message.contains("500"),
+        "{:?}",
+        response.errors
+    );
+    assert_eq!(data(query).await, fetched);
+}
+
+#[tokio::test]
+async fn server_error() {
+    let message = error(r"{ item(league: HARDCORE) { name } }").await;
+    assert!(message.contains("500"), "{message}");
+}
+
+#[tokio::test]
+async fn malformed_payload() {
+    let message = error(r"{ currency(league: RUTHLESS) { name } }").await;
+    assert!(message.starts_with("could not parse data"), "{message}");
+}
+
+#[tokio::test]
+async fn timeout() {
+    let message = error(r"{ item(league: HARDCORE_RUTHLESS) { name } }").await;
+    assert!(message.starts_with("could not fetch data"), "{message}");
+}
+
+#[tokio::test]
+async fn health_and_metrics() {
+    data(r"{ item(league: STANDARD) { name } }").await;
+    let base_url = serve_app(AppConfig {
+        leagues: vec![League::Hardcore],
+        ..AppConfig::default()
+    })
+    .await;
+    let get = |path: &str| reqwest::get(format!("{base_url}{path}"));
+
+    let healthz = get("/healthz").await.expect("healthz");
+    assert_eq!(healthz.status(), StatusCode::OK);
+
+    // hardcore fails with 500, so it is never loaded
+    let readyz = get("/readyz").await.expect("readyz");
+    assert_eq!(readyz.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: Value = readyz.json().await.expect("readiness");
+    assert_eq!(
+        readiness["loading"],
+        json!(["Hardcore Item", "Hardcore Currency"])
+    );
+
+    let metrics = get("/metrics")
+        .await
+        .expect("metrics")
+        .text()
+        .await
+        .expect("metrics text");
+    for series in [
+        r#"poe_api_graphql_operations_total{status="ok",type="query"}"#,
+        r#"poe_api_graphql_rows_returned_count{field="QueryRoot.item"}"#,
+        r#"poe_api_upstream_requests_total{endpoint="UniqueAccessory",overview="item",status="200"}"#,
+        r#"poe_api_cache_age_seconds{kind="item",league="STANDARD"}"#,
+    ] {
+        assert!(metrics.contains(series), "{series} missing from\n{metrics}");
+    }
+}
+
+#[tokio::test]
+async fn ready_while_an_endpoint_fails() {
+    data(r"{ item(league: STANDARD) { name } currency(league: STANDARD) { name } }").await;
+    // the request times are in seconds, so the failure comes after the last success
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let message = error(r"{ item(league: HARDCORE) { name } }").await;
+    assert!(message.contains("500"), "{message}");
+    let base_url = serve_app(AppConfig {
+        leagues: vec![League::Standard],
+        ..AppConfig::default()
+    })
+    .await;
+
+    // poe.ninja answered the standard league, so it is still reachable
+    let readyz = reqwest::get(format!("{base_url}/readyz"))
+        .await
+        .expect("readyz");
+    assert_eq!(readyz.status(), StatusCode::OK);
+    let readiness: Value = readyz.json().await.expect("readiness");
+    assert_eq!(readiness["upstreamReachable"], true);
+    assert!(readiness["lastUpstreamFailure"].is_u64());
+}
+
+#[tokio::test]
+async fn api_keys_and_rate_limits() {
+    let base_url = serve_app(AppConfig {
+        access: AccessConfig {
+            api_keys: vec![parse_api_key("bot:s3cret:2").expect("key")],
+            deny_anonymous: true,
+            ..AccessConfig::default()
+        },
+        ..AppConfig::default()
+    })
+    .await;
+    let client = reqwest::Client::new();
+    let quota = json!({ "query": "{ quota { key limit remaining resetsIn } }" });
+
+    let anonymous = client
+        .post(&base_url)
+        .json(&quota)
+        .send()
+        .await
+        .expect("post");
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    let wrong = client
+        .get(format!("{base_url}/api/openapi.json?api_key=guess"))
+        .send()
+        .await
+        .expect("get");
+    assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+    // health checks are open to the orchestrator
+    let healthz = client
+        .get(format!("{base_url}/healthz"))
+        .send()
+        .await
+        .expect("healthz");
+    assert_eq!(healthz.status(), StatusCode::OK);
+
+    let first = client
+        .post(&base_url)
+        .header("X-API-Key", "s3cret")
+        .json(&quota)
+        .send()
+        .await
+        .expect("post");
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.headers()["x-ratelimit-remaining"], "1");
+    let body: Value = first.json().await.expect("quota");
+    assert_eq!(body["data"]["quota"]["key"], "bot");
+    assert_eq!(body["data"]["quota"]["limit"], 2);
+    assert_eq!(body["data"]["quota"]["remaining"], 1);
+
+    let second = client
+        .post(format!("{base_url}/?api_key=s3cret"))
+        .json(&quota)
+        .send()
+        .await
+        .expect("post");
+    let body: Value = second.json().await.expect("quota");
+    assert_eq!(body["data"]["quota"]["remaining"], 0);
+
+    let third = client
+        .post(&base_url)
+        .header("X-API-Key", "s3cret")
+        .json(&quota)
+        .send()
+        .await
+        .expect("post");
+    assert_eq!(third.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(third.headers().contains_key(header::RETRY_AFTER));
+
+    // anonymous requests are counted per address
+    let base_url = serve_app(AppConfig {
+        access: AccessConfig {
+            ip_rate_limit: 1,
+            ..AccessConfig::default()
+        },
+        ..AppConfig::default()
+    })
+    .await;
+    let first: Value = client
+        .post(&base_url)
+        .json(&quota)
+        .send()
+        .await
+        .expect("post")
+        .json()
+        .await
+        .expect("quota");
+    assert_eq!(
+        first["data"]["quota"],
+        json!({ "key": null, "limit": 1, "remaining": 0, "resetsIn": 60 })
+    );
+    let second = client
+        .post(&base_url)
+        .json(&quota)
+        .send()
+        .await
+        .expect("post");
+    assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn query_limits() {
+    let page = data(r"{ item(league: STANDARD, limit: 10, offset: 240) { name } }").await;
+    assert_eq!(page["item"].as_array().expect("items").len(), 7);
+
+    let message =
+        error(r#"{ item(league: STANDARD, where: { name: { regex: "(" } }) { name } }"#).await;
+    assert!(message.contains("invalid regex"), "{message}");
+
+    // deep enough to overflow the stack if it was parsed
+    let nested = (0..40).fold(
+        r#"{ name: { eq: "Mageblood" } }"#.to_string(),
+        |inner, _| format!("{{ not: [{inner}] }}"),
+    );
+    let message = error(&format!(
+        "{{ item(league: STANDARD, where: {nested}) {{ name }} }}"
+    ))
+    .await;
+    assert!(
+        message.starts_with("where filter is nested 41 levels deep"),
+        "{message}"
+    );
+
+    let nested = (0..40).fold(
+        json!({ "name": { "eq": "Mageblood" } }),
+        |inner, _| json!({ "or": [inner] }),
+    );
+    let response = query(
+        Request::new("query($where: ItemWhere) { item(league: STANDARD, where: $where) { name } }")
+            .variables(Variables::from_json(json!({ "where": nested }))),
+    )
+    .await;
+    assert!(
+        response.errors[0]
+            .message
+            .starts_with("where filter is nested 41 levels deep"),
+        "{:?}",
+        response.errors
+    );
+
+    let fields: Vec<_> = (0..500).map(|i| format!("name{i}: name")).collect();
+    let message = error(&format!(
+        "{{ item(league: STANDARD, limit: 1) {{ {} }} }}",
+        fields.join(" ")
+    ))
+    .await;
+    assert!(message.contains("too complex"), "{message}");
+}